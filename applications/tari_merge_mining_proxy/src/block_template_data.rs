@@ -20,17 +20,56 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 use crate::error::MmProxyError;
-use chrono::{self, DateTime, Duration, Utc};
-use std::{collections::HashMap, sync::Arc};
+use chrono::{self, DateTime, Duration, NaiveDateTime, Utc};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 use tari_app_grpc::tari_rpc::{Block, MinerData};
-use tokio::sync::RwLock;
-use tracing::trace;
+use tokio::{
+    sync::{Mutex, RwLock},
+    task,
+};
+use tracing::{info, trace, warn};
 
 pub const LOG_TARGET: &str = "tari_mm_proxy::xmrig";
 
+/// The number of merge mining hashes of removed block templates that are remembered, see
+/// `BlockTemplateRepository::is_retired`
+const MAX_RETIRED_BLOCK_TEMPLATES: usize = 256;
+
 #[derive(Debug, Clone)]
 pub struct BlockTemplateRepository {
     blocks: Arc<RwLock<HashMap<Vec<u8>, BlockTemplateRepositoryItem>>>,
+    /// The merge mining hashes of the most recently removed block templates, oldest first
+    retired: Arc<RwLock<VecDeque<Vec<u8>>>>,
+    persistence: Option<Arc<Persistence>>,
+}
+
+/// The file block templates are written to. Snapshots are numbered in the order they are taken, so that a snapshot is
+/// never written over a newer one.
+#[derive(Debug)]
+struct Persistence {
+    file: PathBuf,
+    next_version: AtomicU64,
+    /// The version of the last snapshot written to the file
+    written_version: Mutex<u64>,
+}
+
+/// The block templates to write to the persistence file
+struct PersistenceSnapshot {
+    version: u64,
+    templates: Vec<PersistedBlockTemplate>,
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +95,87 @@ impl BlockTemplateRepository {
     pub fn new() -> Self {
         Self {
             blocks: Arc::new(RwLock::new(HashMap::new())),
+            retired: Arc::new(RwLock::new(VecDeque::new())),
+            persistence: None,
+        }
+    }
+
+    /// Creates a repository that is written to `file` whenever it changes, so that block templates survive a restart
+    /// of the proxy. Any block templates previously written to `file` are loaded. If they cannot be loaded the
+    /// repository starts out empty.
+    pub fn with_persistence(file: PathBuf) -> Self {
+        let blocks = match load_block_templates(&file) {
+            Ok(blocks) => {
+                if !blocks.is_empty() {
+                    info!(
+                        target: LOG_TARGET,
+                        "Loaded {} block template(s) from {}",
+                        blocks.len(),
+                        file.display()
+                    );
+                }
+                blocks
+            },
+            Err(err) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to load block templates from {}: {}. Starting with no block templates.",
+                    file.display(),
+                    err
+                );
+                HashMap::new()
+            },
+        };
+
+        Self {
+            blocks: Arc::new(RwLock::new(blocks)),
+            retired: Arc::new(RwLock::new(VecDeque::new())),
+            persistence: Some(Arc::new(Persistence {
+                file,
+                next_version: AtomicU64::new(1),
+                written_version: Mutex::new(0),
+            })),
+        }
+    }
+
+    /// Takes a snapshot of the block templates to persist, if there is a persistence file. Must be called while holding
+    /// the write lock on the block templates, so that snapshots are numbered in the order of the changes.
+    fn snapshot(&self, blocks: &HashMap<Vec<u8>, BlockTemplateRepositoryItem>) -> Option<PersistenceSnapshot> {
+        let persistence = self.persistence.as_ref()?;
+        Some(PersistenceSnapshot {
+            version: persistence.next_version.fetch_add(1, Ordering::SeqCst),
+            templates: blocks
+                .iter()
+                .map(|(hash, item)| PersistedBlockTemplate::new(hash, item))
+                .collect(),
+        })
+    }
+
+    /// Writes a snapshot to the persistence file, unless a newer snapshot has already been written. The lock on the
+    /// block templates must not be held. Failing to persist is logged but does not affect mining.
+    async fn persist(&self, snapshot: Option<PersistenceSnapshot>) {
+        let (persistence, snapshot) = match (self.persistence.as_ref(), snapshot) {
+            (Some(persistence), Some(snapshot)) => (persistence, snapshot),
+            _ => return,
+        };
+        let mut written_version = persistence.written_version.lock().await;
+        if *written_version > snapshot.version {
+            return;
+        }
+        let file = persistence.file.clone();
+        let templates = snapshot.templates;
+        let result = task::spawn_blocking(move || save_block_templates(&file, &templates))
+            .await
+            .map_err(MmProxyError::from)
+            .and_then(|result| result);
+        match result {
+            Ok(()) => *written_version = snapshot.version,
+            Err(err) => warn!(
+                target: LOG_TARGET,
+                "Failed to persist block templates to {}: {}",
+                persistence.file.display(),
+                err
+            ),
         }
     }
 
@@ -75,16 +195,30 @@ impl BlockTemplateRepository {
             "Saving blocktemplate with merge mining hash: {:?}",
             hex::encode(&hash)
         );
-        let mut b = self.blocks.write().await;
-        let repository_item = BlockTemplateRepositoryItem::new(block_template);
-        b.insert(hash, repository_item);
+        let snapshot = {
+            let mut b = self.blocks.write().await;
+            let repository_item = BlockTemplateRepositoryItem::new(block_template);
+            b.insert(hash, repository_item);
+            self.snapshot(&b)
+        };
+        self.persist(snapshot).await;
     }
 
     pub async fn remove_outdated(&self) {
         trace!(target: LOG_TARGET, "Removing outdated blocktemplates");
-        let mut b = self.blocks.write().await;
-        let threshold = Utc::now() - Duration::minutes(20);
-        *b = b.drain().filter(|(_, i)| i.datetime() >= threshold).collect();
+        let (outdated, snapshot) = {
+            let mut b = self.blocks.write().await;
+            let threshold = Utc::now() - Duration::minutes(20);
+            let (kept, outdated): (HashMap<_, _>, HashMap<_, _>) =
+                b.drain().partition(|(_, i)| i.datetime() >= threshold);
+            *b = kept;
+            if outdated.is_empty() {
+                return;
+            }
+            (outdated, self.snapshot(&b))
+        };
+        self.persist(snapshot).await;
+        self.retire(outdated.into_iter().map(|(hash, _)| hash)).await;
     }
 
     pub async fn remove<T: AsRef<[u8]>>(&self, hash: T) -> Option<BlockTemplateRepositoryItem> {
@@ -93,8 +227,154 @@ impl BlockTemplateRepository {
             "Blocktemplate removed with merge mining hash {:?}",
             hex::encode(hash.as_ref())
         );
-        let mut b = self.blocks.write().await;
-        b.remove(hash.as_ref())
+        let (item, snapshot) = {
+            let mut b = self.blocks.write().await;
+            let item = b.remove(hash.as_ref());
+            let snapshot = item.as_ref().and_then(|_| self.snapshot(&b));
+            (item, snapshot)
+        };
+        if item.is_some() {
+            self.persist(snapshot).await;
+            self.retire(Some(hash.as_ref().to_vec())).await;
+        }
+        item
+    }
+
+    /// Returns true if a block template with the given merge mining hash was recently removed, either because it was
+    /// submitted or because it became outdated. Unknown hashes that are not retired were never issued by the proxy.
+    pub async fn is_retired<T: AsRef<[u8]>>(&self, hash: T) -> bool {
+        self.retired.read().await.iter().any(|h| h.as_slice() == hash.as_ref())
+    }
+
+    async fn retire<I: IntoIterator<Item = Vec<u8>>>(&self, hashes: I) {
+        let mut retired = self.retired.write().await;
+        retired.extend(hashes);
+        while retired.len() > MAX_RETIRED_BLOCK_TEMPLATES {
+            retired.pop_front();
+        }
+    }
+}
+
+/// The form in which a block template is written to the persistence file. The gRPC messages are stored as hex encoded
+/// protobuf.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedBlockTemplate {
+    merge_mining_hash: String,
+    monero_seed: String,
+    tari_block: String,
+    tari_miner_data: String,
+    monero_difficulty: u64,
+    tari_difficulty: u64,
+    timestamp: i64,
+}
+
+impl PersistedBlockTemplate {
+    fn new(hash: &[u8], item: &BlockTemplateRepositoryItem) -> Self {
+        Self {
+            merge_mining_hash: hex::encode(hash),
+            monero_seed: item.data.monero_seed.clone(),
+            tari_block: hex::encode(encode_message(&item.data.tari_block)),
+            tari_miner_data: hex::encode(encode_message(&item.data.tari_miner_data)),
+            monero_difficulty: item.data.monero_difficulty,
+            tari_difficulty: item.data.tari_difficulty,
+            timestamp: item.datetime.timestamp(),
+        }
+    }
+
+    fn into_repository_item(self) -> Result<(Vec<u8>, BlockTemplateRepositoryItem), MmProxyError> {
+        let tari_block = Block::decode(hex::decode(&self.tari_block)?.as_slice())
+            .map_err(|err| MmProxyError::MissingDataError(format!("Invalid persisted Tari block: {}", err)))?;
+        let tari_miner_data = MinerData::decode(hex::decode(&self.tari_miner_data)?.as_slice())
+            .map_err(|err| MmProxyError::MissingDataError(format!("Invalid persisted miner data: {}", err)))?;
+        let datetime = NaiveDateTime::from_timestamp_opt(self.timestamp, 0).ok_or_else(|| {
+            MmProxyError::MissingDataError(format!("Invalid persisted timestamp: {}", self.timestamp))
+        })?;
+        let item = BlockTemplateRepositoryItem {
+            data: BlockTemplateData {
+                monero_seed: self.monero_seed,
+                tari_block,
+                tari_miner_data,
+                monero_difficulty: self.monero_difficulty,
+                tari_difficulty: self.tari_difficulty,
+            },
+            datetime: DateTime::from_utc(datetime, Utc),
+        };
+        Ok((hex::decode(&self.merge_mining_hash)?, item))
+    }
+}
+
+fn encode_message<T: Message>(msg: &T) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut buf).expect(
+        "prost::Message::encode documentation says it is infallible unless the buffer has insufficient capacity. This \
+         buffer's capacity was set with encoded_len",
+    );
+    buf
+}
+
+/// Loads the block templates written to `file`. A missing file contains no block templates. Block templates that
+/// cannot be read are logged and skipped.
+fn load_block_templates(file: &Path) -> Result<HashMap<Vec<u8>, BlockTemplateRepositoryItem>, MmProxyError> {
+    let contents = match fs::read(file) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let blocks = json::from_slice::<Vec<json::Value>>(&contents)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, value)| {
+            match json::from_value::<PersistedBlockTemplate>(value)
+                .map_err(MmProxyError::from)
+                .and_then(PersistedBlockTemplate::into_repository_item)
+            {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Skipping block template {} in {}: {}",
+                        i,
+                        file.display(),
+                        err
+                    );
+                    None
+                },
+            }
+        })
+        .collect();
+    Ok(blocks)
+}
+
+/// Writes the block templates to `file`. The file is replaced atomically so that a crash while writing does not leave
+/// a partially written file behind.
+fn save_block_templates(file: &Path, templates: &[PersistedBlockTemplate]) -> Result<(), MmProxyError> {
+    let tmp_file = file.with_extension("tmp");
+    fs::write(&tmp_file, json::to_vec(templates)?)?;
+    fs::rename(&tmp_file, file)?;
+    Ok(())
+}
+
+/// The last block template returned to a miner, kept so that it can be reused while the Monero seed it was built with
+/// is still current
+#[derive(Debug, Clone)]
+pub struct CachedBlockTemplate {
+    pub template: json::Value,
+    pub id: String,
+    created_at: Instant,
+}
+
+impl CachedBlockTemplate {
+    pub fn new(template: json::Value, id: String) -> Self {
+        Self {
+            template,
+            id,
+            created_at: Instant::now(),
+        }
+    }
+
+    /// Returns true if the template was created more than `max_age` ago
+    pub fn is_stale(&self, max_age: std::time::Duration) -> bool {
+        self.created_at.elapsed() > max_age
     }
 }
 
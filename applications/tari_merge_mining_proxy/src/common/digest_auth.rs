@@ -0,0 +1,174 @@
+//  Copyright 2021, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Client side HTTP digest access authentication (RFC 2617), used when monerod's RPC is secured with digest auth.
+
+use crate::error::MmProxyError;
+use hyper::header::{HeaderMap, WWW_AUTHENTICATE};
+use md5::{Digest, Md5};
+
+/// A parsed `WWW-Authenticate: Digest ...` challenge
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    /// True if the server offered the `auth` quality of protection
+    pub qop_auth: bool,
+}
+
+impl DigestChallenge {
+    /// Parse the value of a `WWW-Authenticate` header. Only the `MD5` algorithm is supported.
+    pub fn parse(header: &str) -> Result<Self, MmProxyError> {
+        let header = header.trim();
+        let params = match header.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("digest ") => parse_params(&header[7..]),
+            _ => {
+                return Err(MmProxyError::InvalidDigestChallenge(format!(
+                    "not a digest challenge: `{}`",
+                    header
+                )))
+            },
+        };
+        let get = |name: &str| params.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+
+        if let Some(algorithm) = get("algorithm") {
+            if !algorithm.eq_ignore_ascii_case("md5") {
+                return Err(MmProxyError::InvalidDigestChallenge(format!(
+                    "unsupported algorithm `{}`",
+                    algorithm
+                )));
+            }
+        }
+
+        Ok(Self {
+            realm: get("realm").ok_or_else(|| MmProxyError::InvalidDigestChallenge("missing realm".to_string()))?,
+            nonce: get("nonce").ok_or_else(|| MmProxyError::InvalidDigestChallenge("missing nonce".to_string()))?,
+            opaque: get("opaque"),
+            qop_auth: get("qop")
+                .map(|qop| qop.split(',').any(|q| q.trim().eq_ignore_ascii_case("auth")))
+                .unwrap_or(false),
+        })
+    }
+
+    /// Returns the first supported digest challenge in the `WWW-Authenticate` headers of a 401 response. Servers such
+    /// as monerod may offer several challenges (e.g. `MD5` and `MD5-sess`).
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, MmProxyError> {
+        let mut last_err =
+            MmProxyError::InvalidDigestChallenge("response did not contain a WWW-Authenticate header".to_string());
+        for value in headers.get_all(WWW_AUTHENTICATE) {
+            let result = value
+                .to_str()
+                .map_err(|err| MmProxyError::InvalidDigestChallenge(err.to_string()))
+                .and_then(Self::parse);
+            match result {
+                Ok(challenge) => return Ok(challenge),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Returns the `Authorization` header value answering this challenge
+    pub fn authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        nonce_count: u32,
+        cnonce: &str,
+    ) -> String
+    {
+        let ha1 = md5_hex(&format!("{}:{}:{}", username, self.realm, password));
+        let ha2 = md5_hex(&format!("{}:{}", method, uri));
+        let mut header = format!(
+            r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", algorithm=MD5"#,
+            username, self.realm, self.nonce, uri
+        );
+        let response = if self.qop_auth {
+            let nc = format!("{:08x}", nonce_count);
+            header.push_str(&format!(r#", qop=auth, nc={}, cnonce="{}""#, nc, cnonce));
+            md5_hex(&format!("{}:{}:{}:{}:auth:{}", ha1, self.nonce, nc, cnonce, ha2))
+        } else {
+            md5_hex(&format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+        header.push_str(&format!(r#", response="{}""#, response));
+        if let Some(opaque) = self.opaque.as_ref() {
+            header.push_str(&format!(r#", opaque="{}""#, opaque));
+        }
+        header
+    }
+}
+
+fn md5_hex(value: &str) -> String {
+    hex::encode(Md5::digest(value.as_bytes()))
+}
+
+/// Parses a comma separated list of `name=value` or `name="quoted value"` pairs. Names are lowercased.
+fn parse_params(s: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.peek().map(|c| *c == ',' || c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(c) = chars.peek().copied() {
+            if c == '=' || c == ',' {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.peek().copied() {
+                    if c == ',' {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                value = value.trim().to_string();
+            }
+        }
+        params.push((name, value));
+    }
+    params
+}
@@ -20,68 +20,91 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use crate::{common::proxy, error::MmProxyError};
+use hyper::{Body, Response, StatusCode};
 use json::json;
 use serde_json as json;
 
 /// Default accept response for submit block that goes back to XMRig
 /// Refer to XMRig. Do not change existing json values unless it is changed in XMRig.
-pub fn default_block_accept_response(req_id: Option<i64>) -> json::Value {
-    json!({
-       "id": req_id.unwrap_or(-1),
-       "jsonrpc": "2.0",
-       "result": "{}",
-       "status": "OK",
-       "untrusted": false,
-    })
+pub fn default_block_accept_response(req_id: json::Value) -> json::Value {
+    let mut resp = JsonRpcResponse::success(req_id, json!("{}")).to_json();
+    resp["status"] = "OK".into();
+    resp["untrusted"] = false.into();
+    resp
 }
 
-/// Create a JSON RPC success response
-/// More info: https://www.jsonrpc.org/specification#response_object
-pub fn success_response(req_id: Option<i64>, result: json::Value) -> json::Value {
-    json!({
-       "id": req_id.unwrap_or(-1),
-       "jsonrpc": "2.0",
-       "result": result,
-    })
+/// Response for a submitted block whose Tari block template was issued by the proxy but is no longer held, usually
+/// because it expired between the miner getting work and submitting it. This is not an error, the miner should simply
+/// request new work.
+pub fn stale_block_response(req_id: json::Value) -> json::Value {
+    JsonRpcResponse::success(req_id, json!({ "status": "STALE", "untrusted": false })).to_json()
+}
+
+#[derive(Debug, Clone)]
+enum JsonRpcPayload {
+    Result(json::Value),
+    Error(json::Value),
 }
 
-/// Create a standard JSON RPC error response
-/// More info: https://www.jsonrpc.org/specification#error_object
-pub fn standard_error_response(
-    req_id: Option<i64>,
-    err: jsonrpc::error::StandardError,
-    data: Option<json::Value>,
-) -> json::Value
-{
-    let err = jsonrpc::error::standard_error(err, data);
-    json!({
-        "id":  req_id.unwrap_or(-1),
-        "jsonrpc": "2.0",
-        "error": err,
-    })
+/// Builds a JSON RPC 2.0 response envelope. The request id is echoed back exactly as the client sent it, which may be
+/// a number, a string or null.
+/// More info: https://www.jsonrpc.org/specification#response_object
+#[derive(Debug, Clone)]
+pub struct JsonRpcResponse {
+    id: json::Value,
+    status: StatusCode,
+    payload: JsonRpcPayload,
 }
 
-/// Create a JSON RPC error response
-/// More info: https://www.jsonrpc.org/specification#error_object
-pub fn error_response(
-    req_id: Option<i64>,
-    err_code: i32,
-    err_message: &str,
-    err_data: Option<json::Value>,
-) -> json::Value
-{
-    let mut err = json!({
-        "code": err_code,
-        "message": err_message,
-    });
+impl JsonRpcResponse {
+    pub fn success(id: json::Value, result: json::Value) -> Self {
+        Self {
+            id,
+            status: StatusCode::OK,
+            payload: JsonRpcPayload::Result(result),
+        }
+    }
+
+    pub fn error(id: json::Value, code: i32, message: &str) -> Self {
+        Self {
+            id,
+            status: StatusCode::OK,
+            payload: JsonRpcPayload::Error(json!({ "code": code, "message": message })),
+        }
+    }
+
+    pub fn standard_error(id: json::Value, err: jsonrpc::error::StandardError, data: Option<json::Value>) -> Self {
+        Self {
+            id,
+            status: StatusCode::OK,
+            payload: JsonRpcPayload::Error(json!(jsonrpc::error::standard_error(err, data))),
+        }
+    }
 
-    if let Some(d) = err_data {
-        err["error"]["data"] = d;
+    /// Set the HTTP status of the response. Defaults to 200 OK, as JSON RPC errors are reported in the body.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
     }
 
-    json!({
-        "id":  req_id.unwrap_or(-1),
-        "jsonrpc": "2.0",
-        "error": err
-    })
+    pub fn to_json(&self) -> json::Value {
+        match &self.payload {
+            JsonRpcPayload::Result(result) => json!({
+                "id": self.id,
+                "jsonrpc": "2.0",
+                "result": result,
+            }),
+            JsonRpcPayload::Error(err) => json!({
+                "id": self.id,
+                "jsonrpc": "2.0",
+                "error": err,
+            }),
+        }
+    }
+
+    /// Create a new HTTP response with the JSON content type and content length set
+    pub fn into_response(self) -> Result<Response<Body>, MmProxyError> {
+        proxy::json_response(self.status, &self.to_json())
+    }
 }
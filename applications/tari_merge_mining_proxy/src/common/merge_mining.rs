@@ -23,7 +23,7 @@
 use crate::error::MmProxyError;
 use monero::{
     blockdata::{transaction::SubField, Block},
-    consensus::{deserialize, serialize},
+    consensus::{deserialize, encode::VarInt, serialize},
     cryptonote::hash::Hash,
 };
 use std::convert::TryFrom;
@@ -83,6 +83,32 @@ pub fn add_coinbase(
     }
 }
 
+/// Returns the Tari merge mining hash from the coinbase of a Monero block, or a description of why the merge mining tag
+/// is missing or malformed
+pub fn check_merge_mining_tag(monero: &Block) -> Result<Hash, String> {
+    let tags = monero
+        .miner_tx
+        .prefix
+        .extra
+        .0
+        .iter()
+        .filter_map(|item| match item {
+            SubField::MergeMining(depth, merge_mining_hash) => Some((depth, merge_mining_hash)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    match tags.as_slice() {
+        [] => Err("No merge mining tag found in coinbase".to_string()),
+        [(depth, merge_mining_hash)] if **depth == VarInt(0) => Ok(**merge_mining_hash),
+        [(depth, _)] => Err(format!("Merge mining tag has depth {}, expected 0", depth.0)),
+        _ => Err(format!(
+            "Found {} merge mining tags in coinbase, expected 1",
+            tags.len()
+        )),
+    }
+}
+
 pub fn extract_tari_hash(monero: &Block) -> Option<&Hash> {
     for item in monero.miner_tx.prefix.extra.0.iter() {
         if let SubField::MergeMining(_depth, merge_mining_hash) = item {
@@ -21,9 +21,12 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use hex::FromHexError;
-use std::io;
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
 use tari_common::{ConfigError, ConfigurationError};
-use tari_core::{proof_of_work::monero_rx::MergeMineError, transactions::CoinbaseBuildError};
+use tari_core::{
+    proof_of_work::{monero_rx::MergeMineError, PowAlgorithm},
+    transactions::CoinbaseBuildError,
+};
 use thiserror::Error;
 use tonic::transport;
 
@@ -50,8 +53,18 @@ pub enum MmProxyError {
     HyperError(#[from] hyper::Error),
     #[error("Invalid monerod response: {0}")]
     InvalidMonerodResponse(String),
+    #[error("Monerod returned JSON-RPC error {code}: {message}")]
+    MonerodRpcError { code: i64, message: String },
     #[error("Failed to send request to monerod: {0}")]
     MonerodRequestFailed(reqwest::Error),
+    #[error("Monerod responded with status {status} and unexpected content type `{got}`: {body_snippet}")]
+    UnexpectedContentType {
+        got: String,
+        status: reqwest::StatusCode,
+        body_snippet: String,
+    },
+    #[error("Invalid digest authentication challenge from monerod: {0}")]
+    InvalidDigestChallenge(String),
     #[error("GRPC request failed with `{status}` {details}")]
     GrpcRequestError {
         #[source]
@@ -72,6 +85,18 @@ pub enum MmProxyError {
     CoinbaseBuilderError(#[from] CoinbaseBuildError),
     #[error("Unexpected Tari base node response: {0}")]
     UnexpectedTariBaseNodeResponse(String),
+    #[error("{0}")]
+    SharedError(Arc<MmProxyError>),
+    #[error("Base node returned a block template for PoW algorithm {got}, expected {expected:?}")]
+    UnexpectedPowAlgorithm { expected: PowAlgorithm, got: u64 },
+    #[error("The proxy server stopped unexpectedly")]
+    ServerStopped,
+    #[error("TLS error: {0}")]
+    TlsError(#[from] native_tls::Error),
+    #[error("Base node {address} did not respond to the block submission within {timeout:.0?}")]
+    SubmitBlockTimeout { address: SocketAddr, timeout: Duration },
+    #[error("Blocking task failed: {0}")]
+    BlockingTaskError(#[from] tokio::task::JoinError),
 }
 
 impl From<tonic::Status> for MmProxyError {
@@ -32,50 +32,156 @@ mod block_template_data;
 mod common;
 mod error;
 mod proxy;
+mod readiness;
+mod response_transform;
+mod single_flight;
+mod stats;
+mod task_set;
+mod tls;
 
 #[cfg(test)]
 mod test;
 
-use crate::{block_template_data::BlockTemplateRepository, error::MmProxyError};
-use futures::future;
-use hyper::{service::make_service_fn, Server};
+use crate::{
+    block_template_data::BlockTemplateRepository,
+    error::MmProxyError,
+    readiness::ReadinessGate,
+    response_transform::ResponseTransform,
+    task_set::ProxyTaskSet,
+    tls::TlsConnection,
+};
+use futures::{channel::oneshot, future, future::Either, Future, FutureExt};
+use hyper::{
+    server::{accept, conn::AddrStream},
+    service::make_service_fn,
+    Server,
+};
 use proxy::{MergeMiningProxyConfig, MergeMiningProxyService};
-use std::{convert::Infallible, io};
+use std::{
+    convert::{Infallible, TryFrom},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+};
 use structopt::StructOpt;
 use tari_common::{configuration::bootstrap::ApplicationType, ConfigBootstrap, GlobalConfig};
+use tokio::net::TcpListener;
 
 #[tokio_macros::main]
 async fn main() -> Result<(), MmProxyError> {
     let config = initialize()?;
 
-    let config = MergeMiningProxyConfig::from(config);
+    let config = MergeMiningProxyConfig::try_from(config)?;
     let addr = config.proxy_host_address;
+    let drain_timeout = config.proxy_drain_timeout;
 
-    let xmrig_service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+    let tls_acceptor = match config.proxy_tls_identity_file.as_ref() {
+        Some(file) => Some(tls::load_tls_acceptor(file, &config.proxy_tls_identity_password)?),
+        None => None,
+    };
+
+    let block_templates = match config.block_templates_file.clone() {
+        Some(file) => BlockTemplateRepository::with_persistence(file),
+        None => BlockTemplateRepository::new(),
+    };
+    let response_transforms = config
+        .proxy_response_fields
+        .iter()
+        .map(|field| Arc::new(field.clone()) as Arc<dyn ResponseTransform>)
+        .collect();
+    let xmrig_service = MergeMiningProxyService::new(config, block_templates)
+        .with_response_transforms(response_transforms)
+        .with_readiness_gate(ReadinessGate::new());
     if !xmrig_service.check_connections(&mut io::stdout()).await {
         println!(
             "Warning: some services have not been started or are mis-configured in the proxy config. The proxy will \
              remain running and connect to these services on demand."
         );
     }
-    let service = make_service_fn(|_conn| future::ready(Result::<_, Infallible>::Ok(xmrig_service.clone())));
+    let warm_up_service = xmrig_service.clone();
+    let draining_service = xmrig_service.clone();
+
+    let mut tasks = ProxyTaskSet::new();
+    let graceful_shutdown = tasks.shutdown_signal().map(|_| ());
+    let server: Pin<Box<dyn Future<Output = Result<(), hyper::Error>> + Send>> = match tls_acceptor {
+        Some(acceptor) => match TcpListener::bind(&addr).await {
+            Ok(listener) => {
+                println!("Listening on {} (TLS)...", addr);
+                let service = make_service_fn(move |conn: &TlsConnection| {
+                    future::ready(Result::<_, Infallible>::Ok(
+                        xmrig_service.with_remote_addr(conn.remote_addr()),
+                    ))
+                });
+                let incoming = accept::from_stream(tls::tls_incoming(listener, acceptor));
+                Box::pin(
+                    Server::builder(incoming)
+                        .serve(service)
+                        .with_graceful_shutdown(graceful_shutdown),
+                )
+            },
+            Err(err) => {
+                print_bind_error(&addr);
+                return Err(err.into());
+            },
+        },
+        None => match Server::try_bind(&addr) {
+            Ok(builder) => {
+                println!("Listening on {}...", addr);
+                let service = make_service_fn(move |conn: &AddrStream| {
+                    future::ready(Result::<_, Infallible>::Ok(
+                        xmrig_service.with_remote_addr(conn.remote_addr()),
+                    ))
+                });
+                Box::pin(builder.serve(service).with_graceful_shutdown(graceful_shutdown))
+            },
+            Err(err) => {
+                print_bind_error(&addr);
+                return Err(err.into());
+            },
+        },
+    };
+
+    let shutdown_signal = tasks.shutdown_signal();
+    tasks.spawn("warm_up", async move {
+        future::select(Box::pin(warm_up_service.warm_up()), shutdown_signal).await;
+    });
+    let (server_result_tx, server_result_rx) = oneshot::channel();
+    tasks.spawn("proxy_server", async move {
+        let _ = server_result_tx.send(server.await);
+    });
 
-    match Server::try_bind(&addr) {
-        Ok(builder) => {
-            println!("Listening on {}...", addr);
-            builder.serve(service).await?;
-            Ok(())
+    // Run until Ctrl-C is pressed or the server stops by itself
+    let result = match future::select(Box::pin(tokio::signal::ctrl_c()), server_result_rx).await {
+        Either::Left((signal, _)) => {
+            println!("Shutting down...");
+            signal.map_err(MmProxyError::from)
         },
-        Err(err) => {
-            println!("Fatal: Cannot bind to '{}'.", addr);
-            println!("It may be part of a Port Exclusion Range. Please try to use another port for the");
-            println!("'proxy_host_address' in 'config/config.toml' and for the applicable XMRig '[pools][url]' or");
-            println!("[pools][self-select]' config setting that can be found  in 'config/xmrig_config_***.json' or");
-            println!("'<xmrig folder>/config.json'.");
-            println!();
+        Either::Right((Ok(Err(err)), _)) => {
+            println!("Fatal: The proxy server stopped unexpectedly: {}", err);
             Err(err.into())
         },
+        Either::Right(_) => {
+            println!("Fatal: The proxy server stopped unexpectedly");
+            Err(MmProxyError::ServerStopped)
+        },
+    };
+
+    draining_service.start_draining();
+    let not_stopped = tasks.shutdown(drain_timeout).await;
+    if !not_stopped.is_empty() {
+        println!("Some tasks did not stop in time: {}", not_stopped.join(", "));
     }
+    result
+}
+
+fn print_bind_error(addr: &SocketAddr) {
+    println!("Fatal: Cannot bind to '{}'.", addr);
+    println!("It may be part of a Port Exclusion Range. Please try to use another port for the");
+    println!("'proxy_host_address' in 'config/config.toml' and for the applicable XMRig '[pools][url]' or");
+    println!("[pools][self-select]' config setting that can be found  in 'config/xmrig_config_***.json' or");
+    println!("'<xmrig folder>/config.json'.");
+    println!();
 }
 
 /// Loads the configuration and sets up logging
@@ -21,38 +21,59 @@
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
-    block_template_data::{BlockTemplateDataBuilder, BlockTemplateRepository},
-    common::{json_rpc, merge_mining, monero_rpc::CoreRpcErrorCode, proxy, proxy::convert_json_to_hyper_json_response},
+    block_template_data::{BlockTemplateDataBuilder, BlockTemplateRepository, CachedBlockTemplate},
+    common::{
+        digest_auth::DigestChallenge,
+        json_rpc,
+        json_rpc::JsonRpcResponse,
+        merge_mining,
+        monero_rpc::CoreRpcErrorCode,
+        proxy,
+        proxy::convert_json_to_hyper_json_response,
+    },
     error::MmProxyError,
+    readiness::ReadinessGate,
+    response_transform::{ResponseTransform, SetResponseField},
+    single_flight::SingleFlight,
+    stats::{DifficultyHistory, ProxyStats},
 };
 use bytes::Bytes;
-use futures::TryFutureExt;
+use futures::{future, TryFutureExt};
 use hyper::{service::Service, Body, Method, Request, Response, StatusCode, Uri};
 use json::json;
 use jsonrpc::error::StandardError;
-use reqwest::{header, ResponseBuilderExt, Url};
+use reqwest::{
+    header::{self, HeaderValue},
+    ResponseBuilderExt,
+    Url,
+};
 use serde_json as json;
 use std::{
     cmp,
     cmp::min,
     convert::TryFrom,
+    fmt,
     future::Future,
     io::Write,
+    iter,
     net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tari_app_grpc::{tari_rpc as grpc, tari_rpc::GetCoinbaseRequest};
-use tari_common::{GlobalConfig, Network};
+use tari_common::{ConfigurationError, GlobalConfig, Network};
 use tari_core::{
     blocks::{Block, NewBlockTemplate},
-    proof_of_work::monero_rx,
+    proof_of_work::{monero_rx, PowAlgorithm},
 };
 use tari_utilities::hex::Hex;
+use tokio::{sync::RwLock, time};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 const LOG_TARGET: &str = "tari_mm_proxy::proxy";
@@ -60,6 +81,12 @@ const LOG_TARGET: &str = "tari_mm_proxy::proxy";
 pub(crate) const MMPROXY_AUX_KEY_NAME: &str = "_aux";
 /// The identifier used to identify the tari aux chain data
 const TARI_CHAIN_ID: &str = "xtr";
+/// The name of the file, in the data directory, that block templates are persisted to
+const BLOCK_TEMPLATES_FILE_NAME: &str = "mm_proxy_block_templates.json";
+/// The time to wait before retrying a failed warm-up
+const WARM_UP_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// The time each base node is given to accept a submitted block, so that a slow mirror cannot hold up the response
+const SUBMIT_BLOCK_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub struct MergeMiningProxyConfig {
@@ -68,26 +95,268 @@ pub struct MergeMiningProxyConfig {
     pub monerod_username: String,
     pub monerod_password: String,
     pub monerod_use_auth: bool,
+    pub monerod_auth_scheme: MonerodAuthScheme,
+    pub monerod_validate_content_type: bool,
     pub grpc_base_node_address: SocketAddr,
     pub grpc_console_wallet_address: SocketAddr,
     pub proxy_host_address: SocketAddr,
     pub proxy_submit_to_origin: bool,
+    pub proxy_enable_debug_endpoints: bool,
+    pub coinbase_failure_policy: CoinbaseFailurePolicy,
+    pub proxy_report_stale_submissions: bool,
+    pub proxy_monero_seed_max_age: Duration,
+    /// The client address ranges allowed to use the proxy. An empty list allows all clients.
+    pub allowed_client_cidrs: Vec<cidr::AnyIpCidr>,
+    /// The PoW algorithm requested from the base node for new block templates
+    pub pow_algo: PowAlgorithm,
+    pub proxy_verify_get_block: bool,
+    /// The time in-flight requests are given to complete when the proxy shuts down
+    pub proxy_drain_timeout: Duration,
+    /// The time a request waits for the proxy to warm up before it is refused
+    pub proxy_warm_up_wait_timeout: Duration,
+    /// The number of block template difficulties kept for the `/difficulty_history` endpoint
+    pub proxy_difficulty_history_size: usize,
+    /// The file block templates are persisted to, if persistence is enabled
+    pub block_templates_file: Option<PathBuf>,
+    /// Stream monerod responses that the proxy does not inspect to the miner without parsing them
+    pub proxy_passthrough_unhandled_responses: bool,
+    /// Additional base nodes that found blocks are submitted to, alongside the base node at `grpc_base_node_address`
+    pub proxy_mirror_submit_base_node_addresses: Vec<SocketAddr>,
+    /// The PKCS#12 identity miners are served with over TLS. Plaintext HTTP is served if this is not set.
+    pub proxy_tls_identity_file: Option<PathBuf>,
+    pub proxy_tls_identity_password: String,
+    /// Fields that are set on every JSON response returned to the miner
+    pub proxy_response_fields: Vec<SetResponseField>,
     pub wait_for_initial_sync_at_startup: bool,
 }
 
-impl From<GlobalConfig> for MergeMiningProxyConfig {
-    fn from(config: GlobalConfig) -> Self {
-        Self {
+impl TryFrom<GlobalConfig> for MergeMiningProxyConfig {
+    type Error = MmProxyError;
+
+    fn try_from(config: GlobalConfig) -> Result<Self, Self::Error> {
+        let allowed_client_cidrs = config
+            .proxy_allowed_client_cidrs
+            .iter()
+            .map(|s| cidr::AnyIpCidr::from_str(s))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                ConfigurationError::new("merge_mining_proxy.proxy_allowed_client_cidrs", &err.to_string())
+            })?;
+        let proxy_mirror_submit_base_node_addresses = config
+            .proxy_mirror_submit_base_node_addresses
+            .iter()
+            .map(|s| SocketAddr::from_str(s))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                ConfigurationError::new(
+                    "merge_mining_proxy.proxy_mirror_submit_base_node_addresses",
+                    &err.to_string(),
+                )
+            })?;
+        let proxy_response_fields = config
+            .proxy_response_fields
+            .iter()
+            .map(|s| SetResponseField::from_str(s))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| ConfigurationError::new("merge_mining_proxy.proxy_response_fields", &err))?;
+        let pow_algo = parse_pow_algo(&config.proxy_pow_algo)?;
+
+        Ok(Self {
             network: config.network,
             monerod_url: config.monerod_url,
             monerod_username: config.monerod_username,
             monerod_password: config.monerod_password,
             monerod_use_auth: config.monerod_use_auth,
+            monerod_auth_scheme: MonerodAuthScheme::try_from(config.monerod_auth_scheme)?,
+            monerod_validate_content_type: config.monerod_validate_content_type,
             grpc_base_node_address: config.grpc_base_node_address,
             grpc_console_wallet_address: config.grpc_console_wallet_address,
             proxy_host_address: config.proxy_host_address,
             proxy_submit_to_origin: config.proxy_submit_to_origin,
+            proxy_enable_debug_endpoints: config.proxy_enable_debug_endpoints,
+            coinbase_failure_policy: CoinbaseFailurePolicy::try_from(config.proxy_coinbase_failure_policy)?,
+            proxy_report_stale_submissions: config.proxy_report_stale_submissions,
+            proxy_monero_seed_max_age: config.proxy_monero_seed_max_age,
+            allowed_client_cidrs,
+            pow_algo,
+            proxy_verify_get_block: config.proxy_verify_get_block,
+            proxy_drain_timeout: config.proxy_drain_timeout,
+            proxy_warm_up_wait_timeout: config.proxy_warm_up_wait_timeout,
+            proxy_difficulty_history_size: config.proxy_difficulty_history_size,
+            block_templates_file: if config.proxy_persist_block_templates {
+                Some(config.data_dir.join(BLOCK_TEMPLATES_FILE_NAME))
+            } else {
+                None
+            },
+            proxy_passthrough_unhandled_responses: config.proxy_passthrough_unhandled_responses,
+            proxy_mirror_submit_base_node_addresses,
+            proxy_tls_identity_file: config.proxy_tls_identity_file,
+            proxy_tls_identity_password: config.proxy_tls_identity_password,
+            proxy_response_fields,
             wait_for_initial_sync_at_startup: config.wait_for_initial_sync_at_startup,
+        })
+    }
+}
+
+/// Determines how a failure to add the coinbase to a new Tari block template is handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoinbaseFailurePolicy {
+    /// Return the error to the miner
+    Fail,
+    /// Return the last successfully built block template, marked as stale
+    ServeLastTemplate,
+}
+
+impl fmt::Display for CoinbaseFailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fail => f.write_str("Fail"),
+            Self::ServeLastTemplate => f.write_str("ServeLastTemplate"),
+        }
+    }
+}
+
+impl TryFrom<String> for CoinbaseFailurePolicy {
+    type Error = ConfigurationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "Fail" => Ok(Self::Fail),
+            "ServeLastTemplate" => Ok(Self::ServeLastTemplate),
+            other => Err(ConfigurationError::new(
+                "merge_mining_proxy.proxy_coinbase_failure_policy",
+                &format!("unsupported coinbase failure policy `{}`", other),
+            )),
+        }
+    }
+}
+
+impl Default for CoinbaseFailurePolicy {
+    fn default() -> Self {
+        Self::Fail
+    }
+}
+
+/// The HTTP authentication scheme used to authenticate with monerod
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonerodAuthScheme {
+    Basic,
+    /// Digest access authentication. The challenge is obtained from monerod's 401 response.
+    Digest,
+}
+
+impl fmt::Display for MonerodAuthScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic => f.write_str("Basic"),
+            Self::Digest => f.write_str("Digest"),
+        }
+    }
+}
+
+impl TryFrom<String> for MonerodAuthScheme {
+    type Error = ConfigurationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "Basic" => Ok(Self::Basic),
+            "Digest" => Ok(Self::Digest),
+            other => Err(ConfigurationError::new(
+                "merge_mining_proxy.monerod_auth_scheme",
+                &format!("unsupported monerod auth scheme `{}`", other),
+            )),
+        }
+    }
+}
+
+impl Default for MonerodAuthScheme {
+    fn default() -> Self {
+        Self::Basic
+    }
+}
+
+impl MergeMiningProxyConfig {
+    /// The placeholder value returned in place of secret configuration values
+    pub const REDACTED: &'static str = "<redacted>";
+
+    /// Returns the configuration as a JSON object with all secrets redacted. Every field is listed explicitly so that
+    /// adding a field to the config requires a decision on whether it is safe to expose.
+    pub fn to_redacted_json(&self) -> json::Value {
+        let Self {
+            network,
+            monerod_url,
+            monerod_username,
+            monerod_password: _,
+            monerod_use_auth,
+            monerod_auth_scheme,
+            monerod_validate_content_type,
+            grpc_base_node_address,
+            grpc_console_wallet_address,
+            proxy_host_address,
+            proxy_submit_to_origin,
+            proxy_enable_debug_endpoints,
+            coinbase_failure_policy,
+            proxy_report_stale_submissions,
+            proxy_monero_seed_max_age,
+            allowed_client_cidrs,
+            pow_algo,
+            proxy_verify_get_block,
+            proxy_drain_timeout,
+            proxy_warm_up_wait_timeout,
+            proxy_difficulty_history_size,
+            block_templates_file,
+            proxy_passthrough_unhandled_responses,
+            proxy_mirror_submit_base_node_addresses,
+            proxy_tls_identity_file,
+            proxy_tls_identity_password: _,
+            proxy_response_fields,
+            wait_for_initial_sync_at_startup,
+        } = self;
+
+        json!({
+            "network": network.to_string(),
+            "monerod_url": monerod_url,
+            "monerod_username": monerod_username,
+            "monerod_password": Self::REDACTED,
+            "monerod_use_auth": monerod_use_auth,
+            "monerod_auth_scheme": monerod_auth_scheme.to_string(),
+            "monerod_validate_content_type": monerod_validate_content_type,
+            "grpc_base_node_address": grpc_base_node_address.to_string(),
+            "grpc_console_wallet_address": grpc_console_wallet_address.to_string(),
+            "proxy_host_address": proxy_host_address.to_string(),
+            "proxy_submit_to_origin": proxy_submit_to_origin,
+            "proxy_enable_debug_endpoints": proxy_enable_debug_endpoints,
+            "coinbase_failure_policy": coinbase_failure_policy.to_string(),
+            "proxy_report_stale_submissions": proxy_report_stale_submissions,
+            "proxy_monero_seed_max_age": proxy_monero_seed_max_age.as_secs(),
+            "allowed_client_cidrs": allowed_client_cidrs.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "pow_algo": format!("{:?}", pow_algo),
+            "proxy_verify_get_block": proxy_verify_get_block,
+            "proxy_drain_timeout": proxy_drain_timeout.as_secs(),
+            "proxy_warm_up_wait_timeout": proxy_warm_up_wait_timeout.as_secs(),
+            "proxy_difficulty_history_size": proxy_difficulty_history_size,
+            "block_templates_file": block_templates_file.as_ref().map(|p| p.display().to_string()),
+            "proxy_passthrough_unhandled_responses": proxy_passthrough_unhandled_responses,
+            "proxy_mirror_submit_base_node_addresses": proxy_mirror_submit_base_node_addresses
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            "proxy_tls_identity_file": proxy_tls_identity_file.as_ref().map(|p| p.display().to_string()),
+            "proxy_tls_identity_password": Self::REDACTED,
+            "proxy_response_fields": proxy_response_fields.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "wait_for_initial_sync_at_startup": wait_for_initial_sync_at_startup,
+        })
+    }
+
+    /// Returns true if a client at `addr` may use the proxy. A client with an unknown address is only allowed if no
+    /// allowlist is configured.
+    pub fn is_client_allowed(&self, addr: Option<SocketAddr>) -> bool {
+        if self.allowed_client_cidrs.is_empty() {
+            return true;
+        }
+        match addr {
+            Some(addr) => self.allowed_client_cidrs.iter().any(|cidr| cidr.contains(&addr.ip())),
+            None => false,
         }
     }
 }
@@ -99,16 +368,76 @@ pub struct MergeMiningProxyService {
 
 impl MergeMiningProxyService {
     pub fn new(config: MergeMiningProxyConfig, block_templates: BlockTemplateRepository) -> Self {
+        let difficulty_history = Arc::new(DifficultyHistory::new(config.proxy_difficulty_history_size));
         Self {
             inner: InnerService {
                 config,
                 block_templates,
                 http_client: reqwest::Client::new(),
                 initial_sync_achieved: Arc::new(AtomicBool::new(false)),
+                last_block_template: Arc::new(RwLock::new(None)),
+                stats: Arc::new(ProxyStats::new()),
+                difficulty_history,
+                remote_addr: None,
+                response_transforms: Vec::new(),
+                tari_block_builds: SingleFlight::new(),
+                readiness: None,
+                draining: Arc::new(AtomicBool::new(false)),
             },
         }
     }
 
+    /// Set the transforms that are applied, in order, to the JSON body of every response
+    pub fn with_response_transforms(mut self, transforms: Vec<Arc<dyn ResponseTransform>>) -> Self {
+        self.inner.response_transforms = transforms;
+        self
+    }
+
+    /// Defer requests to monerod until `readiness` is set. The gate is set by [warm_up](Self::warm_up).
+    pub fn with_readiness_gate(mut self, readiness: ReadinessGate) -> Self {
+        self.inner.readiness = Some(readiness);
+        self
+    }
+
+    /// Waits until the base node and the wallet are reachable, retrying until they are, and then sets the readiness
+    /// gate. Returns immediately if there is no readiness gate.
+    pub async fn warm_up(&self) {
+        let readiness = match self.inner.readiness {
+            Some(ref readiness) => readiness,
+            None => return,
+        };
+
+        loop {
+            match self.inner.try_warm_up().await {
+                Ok(()) => {
+                    info!(target: LOG_TARGET, "Warm-up complete, the proxy is ready for miners");
+                    readiness.set_ready();
+                    return;
+                },
+                Err(err) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Proxy is not ready yet ({}), retrying in {:.0?}", err, WARM_UP_RETRY_INTERVAL
+                    );
+                    time::delay_for(WARM_UP_RETRY_INTERVAL).await;
+                },
+            }
+        }
+    }
+
+    /// Starts refusing new requests, other than block submissions, while in-flight requests are allowed to complete.
+    /// This applies to every copy of this service.
+    pub fn start_draining(&self) {
+        self.inner.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns a copy of this service that handles requests from the client connected at `addr`
+    pub fn with_remote_addr(&self, addr: SocketAddr) -> Self {
+        let mut service = self.clone();
+        service.inner.remote_addr = Some(addr);
+        service
+    }
+
     pub async fn check_connections<W: Write>(&self, w: &mut W) -> bool {
         let mut is_success = true;
         let inner = &self.inner;
@@ -217,14 +546,13 @@ impl Service<Request<Body>> for MergeMiningProxyService {
                 Err(err) => {
                     error!(target: LOG_TARGET, "Error handling request: {}", err);
 
-                    Ok(proxy::json_response(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        &json_rpc::standard_error_response(
-                            None,
-                            StandardError::InternalError,
-                            Some(json!({"details": err.to_string()})),
-                        ),
+                    Ok(JsonRpcResponse::standard_error(
+                        json::Value::Null,
+                        StandardError::InternalError,
+                        Some(json!({"details": err.to_string()})),
                     )
+                    .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response()
                     .expect("unexpected failure"))
                 },
             }
@@ -238,6 +566,30 @@ struct InnerService {
     block_templates: BlockTemplateRepository,
     http_client: reqwest::Client,
     initial_sync_achieved: Arc<AtomicBool>,
+    last_block_template: Arc<RwLock<Option<CachedBlockTemplate>>>,
+    stats: Arc<ProxyStats>,
+    difficulty_history: Arc<DifficultyHistory>,
+    remote_addr: Option<SocketAddr>,
+    response_transforms: Vec<Arc<dyn ResponseTransform>>,
+    tari_block_builds: SingleFlight<u64, Result<TariBlockBuild, Arc<MmProxyError>>>,
+    readiness: Option<ReadinessGate>,
+    draining: Arc<AtomicBool>,
+}
+
+/// The Tari block built for a block template, shared by concurrent `get_block_template` requests at the same height
+#[derive(Debug, Clone)]
+struct TariBlockTemplate {
+    miner_data: grpc::MinerData,
+    block: grpc::Block,
+    mining_hash: Vec<u8>,
+    tari_height: u64,
+}
+
+#[derive(Debug, Clone)]
+enum TariBlockBuild {
+    Built(TariBlockTemplate),
+    /// The coinbase could not be added to the template, which is handled by the coinbase failure policy
+    CoinbaseFailed(Arc<MmProxyError>),
 }
 
 impl InnerService {
@@ -284,6 +636,9 @@ impl InnerService {
             "Monero height = #{}, Tari base node height = #{}", json["height"], height
         );
 
+        self.stats.set_tari_height(height);
+        self.stats
+            .set_monero_height(json["height"].as_u64().unwrap_or_default());
         json["height"] = json!(cmp::max(json["height"].as_i64().unwrap_or_default(), height as i64));
 
         Ok(proxy::into_response(parts, &json))
@@ -297,21 +652,23 @@ impl InnerService {
     {
         let request = request.body();
         let (parts, mut json_resp) = monerod_resp.into_parts();
+        if let Err(err) = check_monerod_rpc_error(&json_resp) {
+            // A block that only meets the Tari difficulty is expected to be rejected by monerod, so it is still
+            // submitted to the Tari base node
+            debug!(target: LOG_TARGET, "handle_submit_block: {}", err);
+        }
 
         debug!(target: LOG_TARGET, "handle_submit_block: submit request #{}", request);
         debug!(target: LOG_TARGET, "Params received: #{:?}", request["params"]);
         let params = match request["params"].as_array() {
             Some(v) => v,
             None => {
-                return proxy::json_response(
-                    StatusCode::OK,
-                    &json_rpc::error_response(
-                        request["id"].as_i64(),
-                        CoreRpcErrorCode::WrongParam.into(),
-                        "`params` field is empty or an invalid type for submit block request. Expected an array.",
-                        None,
-                    ),
+                return JsonRpcResponse::error(
+                    request["id"].clone(),
+                    CoreRpcErrorCode::WrongParam.into(),
+                    "`params` field is empty or an invalid type for submit block request. Expected an array.",
                 )
+                .into_response()
             },
         };
 
@@ -331,11 +688,23 @@ impl InnerService {
             let mut block_data = match self.block_templates.get(&hash).await {
                 Some(d) => d,
                 None => {
-                    info!(
-                        target: LOG_TARGET,
-                        "Block `{}` submitted but no matching block template was found, possible duplicate submission",
-                        hex::encode(&hash)
-                    );
+                    if self.block_templates.is_retired(&hash).await {
+                        info!(
+                            target: LOG_TARGET,
+                            "Block `{}` submitted for a block template that was already submitted or has expired",
+                            hex::encode(&hash)
+                        );
+                        if self.config.proxy_report_stale_submissions {
+                            json_resp = json_rpc::stale_block_response(request["id"].clone());
+                        }
+                    } else {
+                        warn!(
+                            target: LOG_TARGET,
+                            "Block `{}` submitted but no block template with this merge mining hash was issued by the \
+                             proxy",
+                            hex::encode(&hash)
+                        );
+                    }
                     continue;
                 },
             };
@@ -346,19 +715,19 @@ impl InnerService {
             let height = header_mut.height;
             header_mut.pow.as_mut().unwrap().pow_data = bincode::serialize(&monero_data)?;
 
-            let mut base_node_client = self.connect_grpc_client().await?;
             let start = Instant::now();
-            match base_node_client.submit_block(block_data.tari_block).await {
+            match self.submit_block_to_base_nodes(block_data.tari_block).await {
                 Ok(resp) => {
+                    self.stats.record_submission(true);
                     if !self.config.proxy_submit_to_origin {
                         // self-select related, do not change.
-                        json_resp = json_rpc::default_block_accept_response(request["id"].as_i64());
+                        json_resp = json_rpc::default_block_accept_response(request["id"].clone());
                     } else {
-                        json_resp = json_rpc::success_response(
-                            request["id"].as_i64(),
+                        json_resp = JsonRpcResponse::success(
+                            request["id"].clone(),
                             json!({ "status": "OK", "untrusted": !self.initial_sync_achieved.load(Ordering::Relaxed) }),
-                        );
-                        let resp = resp.into_inner();
+                        )
+                        .to_json();
                         json_resp = append_aux_chain_data(
                             json_resp,
                             json!({"id": TARI_CHAIN_ID, "block_hash": resp.block_hash.to_hex()}),
@@ -373,6 +742,7 @@ impl InnerService {
                     self.block_templates.remove(&hash).await;
                 },
                 Err(err) => {
+                    self.stats.record_submission(false);
                     debug!(
                         target: LOG_TARGET,
                         "Problem submitting block #{} to Tari node, responded in  {:.0?} (SubmitBlock): {}",
@@ -384,12 +754,12 @@ impl InnerService {
                     if !self.config.proxy_submit_to_origin {
                         // When "submit to origin" is turned off the block is never submitted to monerod, and so we need
                         // to construct an error message here.
-                        json_resp = json_rpc::error_response(
-                            request["id"].as_i64(),
+                        json_resp = JsonRpcResponse::error(
+                            request["id"].clone(),
                             CoreRpcErrorCode::BlockNotAccepted.into(),
                             "Block not accepted",
-                            None,
-                        );
+                        )
+                        .to_json();
                     }
                 },
             }
@@ -401,8 +771,45 @@ impl InnerService {
         Ok(proxy::into_response(parts, &json_resp))
     }
 
+    /// Submits `block` to the base node and to every mirror base node concurrently. The submission succeeds if any
+    /// base node accepts the block, see [select_block_submission_result].
+    async fn submit_block_to_base_nodes(&self, block: grpc::Block) -> Result<grpc::SubmitBlockResponse, MmProxyError> {
+        let addresses = iter::once(self.config.grpc_base_node_address)
+            .chain(self.config.proxy_mirror_submit_base_node_addresses.iter().copied());
+        let results = future::join_all(addresses.map(|address| {
+            let block = block.clone();
+            async move {
+                let result = time::timeout(SUBMIT_BLOCK_TIMEOUT, submit_block_to_base_node(address, block))
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(MmProxyError::SubmitBlockTimeout {
+                            address,
+                            timeout: SUBMIT_BLOCK_TIMEOUT,
+                        })
+                    });
+                (address, result)
+            }
+        }))
+        .await;
+
+        if !self.config.proxy_mirror_submit_base_node_addresses.is_empty() {
+            for (address, result) in &results {
+                match result {
+                    Ok(_) => info!(target: LOG_TARGET, "Base node {} accepted the submitted block", address),
+                    Err(err) => warn!(
+                        target: LOG_TARGET,
+                        "Base node {} did not accept the submitted block: {}", address, err
+                    ),
+                }
+            }
+        }
+
+        select_block_submission_result(results)
+    }
+
     async fn handle_get_block_template(
         &self,
+        request: Request<json::Value>,
         monerod_resp: Response<json::Value>,
     ) -> Result<Response<Body>, MmProxyError>
     {
@@ -412,9 +819,10 @@ impl InnerService {
             "handle_get_block_template: monero block #{}", monerod_resp["result"]["height"]
         );
 
-        // If monderod returned an error, there is nothing further for us to do
-        if !monerod_resp["error"].is_null() {
-            return Ok(proxy::into_response(parts, &monerod_resp));
+        // If monerod returned an error, there is nothing further for us to do
+        check_monerod_rpc_error(&monerod_resp)?;
+        if let Some(height) = monerod_resp["result"]["height"].as_u64() {
+            self.stats.set_monero_height(height);
         }
 
         if monerod_resp["result"]["difficulty"].is_null() {
@@ -441,6 +849,163 @@ impl InnerService {
             ));
         }
 
+        let monero_height = monerod_resp["result"]["height"].as_u64().unwrap_or_default();
+        let seed_hash = monerod_resp["result"]["seed_hash"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut grpc_client = self.connect_grpc_client().await?;
+        let tip_height = grpc_client
+            .get_tip_info(grpc::Empty {})
+            .await?
+            .into_inner()
+            .metadata
+            .map(|meta| meta.height_of_longest_chain)
+            .ok_or_else(|| MmProxyError::GrpcResponseMissingField("metadata"))?;
+
+        // A reconnecting miner that already has the current template does not need it to be rebuilt or resent
+        if let Some(if_none_match) = request.headers().get(header::IF_NONE_MATCH) {
+            if let Some(last_id) = self.last_fresh_block_template().await.map(|t| t.id) {
+                let template_id = block_template_id(tip_height + 1, monero_height, &seed_hash);
+                if template_id == last_id && is_template_id_match(if_none_match, &template_id) {
+                    debug!(target: LOG_TARGET, "Block template {} is unchanged", template_id);
+                    return Ok(Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .header(header::ETAG, template_id)
+                        .body(Body::empty())?);
+                }
+            }
+        }
+
+        // Concurrent requests for a template at the same height share a single build from the base node
+        let inner = self.clone();
+        let build = self
+            .tari_block_builds
+            .run(tip_height + 1, move || async move {
+                inner.build_tari_block().await.map_err(Arc::new)
+            })
+            .await
+            .map_err(MmProxyError::SharedError)?;
+        let TariBlockTemplate {
+            miner_data,
+            block,
+            mining_hash,
+            tari_height,
+        } = match build {
+            TariBlockBuild::Built(template) => template,
+            TariBlockBuild::CoinbaseFailed(err) => {
+                let last_block_template = self.last_fresh_block_template().await.map(|t| t.template);
+                let resp = fallback_block_template(
+                    self.config.coinbase_failure_policy,
+                    request.body()["id"].clone(),
+                    &monerod_resp,
+                    last_block_template,
+                    MmProxyError::SharedError(err),
+                )?;
+                return Ok(proxy::into_response(parts, &resp));
+            },
+        };
+
+        let block_reward = miner_data.reward;
+        let total_fees = miner_data.total_fees;
+        let tari_difficulty = miner_data.target_difficulty;
+        self.difficulty_history.record(tari_height, tari_difficulty);
+
+        let block_data = BlockTemplateDataBuilder::default();
+        let block_data = block_data.tari_block(block).tari_miner_data(miner_data);
+
+        // Deserialize the block template blob
+        let block_template_blob = &monerod_resp["result"]["blocktemplate_blob"]
+            .to_string()
+            .replace("\"", "");
+        debug!(target: LOG_TARGET, "Deserializing Blocktemplate Blob into Monero Block",);
+        let mut monero_block = merge_mining::deserialize_monero_block_from_hex(block_template_blob)?;
+
+        debug!(target: LOG_TARGET, "Appending Merged Mining Tag",);
+        // Add the Tari merge mining tag to the retrieved block template
+        monero_rx::append_merge_mining_tag(&mut monero_block, &mining_hash)?;
+
+        debug!(target: LOG_TARGET, "Creating blockhashing blob from blocktemplate blob",);
+        // Must be done after the tag is inserted since it will affect the hash of the miner tx
+        let blockhashing_blob = monero_rx::create_blockhashing_blob(&monero_block)?;
+
+        debug!(target: LOG_TARGET, "blockhashing_blob:{}", blockhashing_blob);
+        monerod_resp["result"]["blockhashing_blob"] = blockhashing_blob.into();
+
+        let blocktemplate_blob = merge_mining::serialize_monero_block_to_hex(&monero_block)?;
+        debug!(target: LOG_TARGET, "blocktemplate_blob:{}", block_template_blob);
+        monerod_resp["result"]["blocktemplate_blob"] = blocktemplate_blob.into();
+
+        let seed = monerod_resp["result"]["seed_hash"].to_string().replace("\"", "");
+
+        let block_data = block_data.monero_seed(seed);
+
+        let monero_difficulty = monerod_resp["result"]["difficulty"].as_u64().unwrap_or_default();
+
+        let mining_difficulty = min(monero_difficulty, tari_difficulty);
+
+        let block_data = block_data
+            .monero_difficulty(monero_difficulty)
+            .tari_difficulty(tari_difficulty);
+
+        info!(
+            target: LOG_TARGET,
+            "Difficulties: Tari ({}), Monero({}), Selected({})", tari_difficulty, monero_difficulty, mining_difficulty
+        );
+        monerod_resp["result"]["difficulty"] = mining_difficulty.into();
+        let monerod_resp = add_aux_data(monerod_resp, json!({ "base_difficulty": monero_difficulty }));
+        let monerod_resp = append_aux_chain_data(
+            monerod_resp,
+            json!({
+                "id": TARI_CHAIN_ID,
+                "difficulty": tari_difficulty,
+                "height": tari_height,
+                // The merge mining hash, before the final block hash can be calculated
+                "mining_hash": mining_hash.to_hex(),
+                "miner_reward": block_reward + total_fees,
+            }),
+        );
+
+        self.block_templates.save(mining_hash, block_data.build()?).await;
+        let template_id = block_template_id(tari_height, monero_height, &seed_hash);
+        *self.last_block_template.write().await =
+            Some(CachedBlockTemplate::new(monerod_resp.clone(), template_id.clone()));
+
+        debug!(target: LOG_TARGET, "Returning template result: {}", monerod_resp);
+        let mut resp = proxy::into_response(parts, &monerod_resp);
+        if let Ok(etag) = HeaderValue::from_str(&template_id) {
+            resp.headers_mut().insert(header::ETAG, etag);
+        }
+        Ok(resp)
+    }
+
+    /// Checks that the base node and the wallet are reachable and responding. This does not build a block template,
+    /// because that would request a coinbase from the wallet each time it is retried.
+    async fn try_warm_up(&self) -> Result<(), MmProxyError> {
+        let mut base_node_client = self.connect_grpc_client().await?;
+        base_node_client
+            .get_tip_info(grpc::Empty {})
+            .await
+            .map_err(|status| MmProxyError::GrpcRequestError {
+                status,
+                details: "get_tip_info failed".to_string(),
+            })?;
+
+        let mut wallet_client = self.connect_grpc_wallet_client().await?;
+        wallet_client
+            .identify(grpc::GetIdentityRequest {})
+            .await
+            .map_err(|status| MmProxyError::GrpcRequestError {
+                status,
+                details: "identify failed".to_string(),
+            })?;
+
+        Ok(())
+    }
+
+    /// Requests a new block template from the base node and adds the coinbase from the wallet
+    async fn build_tari_block(&self) -> Result<TariBlockBuild, MmProxyError> {
         let mut grpc_client = self.connect_grpc_client().await?;
 
         // Add merge mining tag on blocktemplate request
@@ -452,8 +1017,9 @@ impl InnerService {
             initial_sync_achieved,
         } = grpc_client
             .get_new_block_template(grpc::NewBlockTemplateRequest {
+                // The gRPC PowAlgos values are the same as the PowAlgorithm discriminants
                 algo: Some(grpc::PowAlgo {
-                    pow_algo: grpc::pow_algo::PowAlgos::Monero.into(),
+                    pow_algo: self.config.pow_algo.as_u64() as i32,
                 }),
                 max_weight: 0,
             })
@@ -467,10 +1033,10 @@ impl InnerService {
         let miner_data = miner_data.ok_or_else(|| MmProxyError::GrpcResponseMissingField("miner_data"))?;
         let new_block_template =
             new_block_template.ok_or_else(|| MmProxyError::GrpcResponseMissingField("new_block_template"))?;
+        check_template_pow_algo(&new_block_template, self.config.pow_algo)?;
 
         let block_reward = miner_data.reward;
         let total_fees = miner_data.total_fees;
-        let tari_difficulty = miner_data.target_difficulty;
 
         if !self.initial_sync_achieved.load(Ordering::Relaxed) {
             if !initial_sync_achieved {
@@ -521,7 +1087,10 @@ impl InnerService {
             })?;
         let coinbase_transaction = coinbase_response.into_inner().transaction;
 
-        let coinbased_block = merge_mining::add_coinbase(coinbase_transaction, template_block)?;
+        let coinbased_block = match merge_mining::add_coinbase(coinbase_transaction, template_block) {
+            Ok(block) => block,
+            Err(err) => return Ok(TariBlockBuild::CoinbaseFailed(Arc::new(err))),
+        };
         debug!(target: LOG_TARGET, "Added coinbase to new block template");
         let block = grpc_client
             .get_new_block(coinbased_block)
@@ -543,71 +1112,29 @@ impl InnerService {
         .map_err(MmProxyError::MissingDataError)?;
         debug!(target: LOG_TARGET, "New block received from Tari: {}", (tari_block));
 
-        let block_data = BlockTemplateDataBuilder::default();
-        let block_data = block_data
-            .tari_block(
-                block
-                    .block
-                    .ok_or_else(|| MmProxyError::GrpcResponseMissingField("block"))?,
-            )
-            .tari_miner_data(miner_data);
-
-        // Deserialize the block template blob
-        let block_template_blob = &monerod_resp["result"]["blocktemplate_blob"]
-            .to_string()
-            .replace("\"", "");
-        debug!(target: LOG_TARGET, "Deserializing Blocktemplate Blob into Monero Block",);
-        let mut monero_block = merge_mining::deserialize_monero_block_from_hex(block_template_blob)?;
-
-        debug!(target: LOG_TARGET, "Appending Merged Mining Tag",);
-        // Add the Tari merge mining tag to the retrieved block template
-        monero_rx::append_merge_mining_tag(&mut monero_block, &mining_hash)?;
-
-        debug!(target: LOG_TARGET, "Creating blockhashing blob from blocktemplate blob",);
-        // Must be done after the tag is inserted since it will affect the hash of the miner tx
-        let blockhashing_blob = monero_rx::create_blockhashing_blob(&monero_block)?;
-
-        debug!(target: LOG_TARGET, "blockhashing_blob:{}", blockhashing_blob);
-        monerod_resp["result"]["blockhashing_blob"] = blockhashing_blob.into();
-
-        let blocktemplate_blob = merge_mining::serialize_monero_block_to_hex(&monero_block)?;
-        debug!(target: LOG_TARGET, "blocktemplate_blob:{}", block_template_blob);
-        monerod_resp["result"]["blocktemplate_blob"] = blocktemplate_blob.into();
-
-        let seed = monerod_resp["result"]["seed_hash"].to_string().replace("\"", "");
-
-        let block_data = block_data.monero_seed(seed);
-
-        let monero_difficulty = monerod_resp["result"]["difficulty"].as_u64().unwrap_or_default();
-
-        let mining_difficulty = min(monero_difficulty, tari_difficulty);
-
-        let block_data = block_data
-            .monero_difficulty(monero_difficulty)
-            .tari_difficulty(tari_difficulty);
-
-        info!(
-            target: LOG_TARGET,
-            "Difficulties: Tari ({}), Monero({}), Selected({})", tari_difficulty, monero_difficulty, mining_difficulty
-        );
-        monerod_resp["result"]["difficulty"] = mining_difficulty.into();
-        let monerod_resp = add_aux_data(monerod_resp, json!({ "base_difficulty": monero_difficulty }));
-        let monerod_resp = append_aux_chain_data(
-            monerod_resp,
-            json!({
-                "id": TARI_CHAIN_ID,
-                "difficulty": tari_difficulty,
-                "height": tari_height,
-                // The merge mining hash, before the final block hash can be calculated
-                "mining_hash": mining_hash.to_hex(),
-                "miner_reward": block_reward + total_fees,
-            }),
-        );
-
-        self.block_templates.save(mining_hash, block_data.build()?).await;
+        Ok(TariBlockBuild::Built(TariBlockTemplate {
+            miner_data,
+            block: block
+                .block
+                .ok_or_else(|| MmProxyError::GrpcResponseMissingField("block"))?,
+            mining_hash,
+            tari_height,
+        }))
+    }
 
-        debug!(target: LOG_TARGET, "Returning template result: {}", monerod_resp);
-        Ok(proxy::into_response(parts, &monerod_resp))
+    /// Returns the last block template if it was built recently enough that its Monero seed can be considered current
+    async fn last_fresh_block_template(&self) -> Option<CachedBlockTemplate> {
+        let max_age = self.config.proxy_monero_seed_max_age;
+        match self.last_block_template.read().await.as_ref() {
+            Some(template) if template.is_stale(max_age) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Cached block template {} is older than {:.0?} and will not be reused", template.id, max_age
+                );
+                None
+            },
+            template => template.cloned(),
+        }
     }
 
     async fn handle_get_block_header_by_hash(
@@ -630,10 +1157,8 @@ impl InnerService {
         let hash = match hash {
             Ok(hash) => hash,
             Err(err) => {
-                return proxy::json_response(
-                    StatusCode::OK,
-                    &json_rpc::error_response(request["id"].as_i64(), CoreRpcErrorCode::WrongParam.into(), err, None),
-                )
+                return JsonRpcResponse::error(request["id"].clone(), CoreRpcErrorCode::WrongParam.into(), err)
+                    .into_response()
             },
         };
 
@@ -666,7 +1191,8 @@ impl InnerService {
                     "[get_header_by_hash] Found tari block header with hash `{}`", hash_hex
                 );
                 let json_resp =
-                    json_rpc::success_response(request["id"].as_i64(), json!({ "block_header": json_block_header }));
+                    JsonRpcResponse::success(request["id"].clone(), json!({ "block_header": json_block_header }))
+                        .to_json();
 
                 let json_resp = append_aux_chain_data(json_resp, json!({ "id": TARI_CHAIN_ID }));
 
@@ -686,6 +1212,41 @@ impl InnerService {
         }
     }
 
+    async fn handle_get_block(&self, monerod_resp: Response<json::Value>) -> Result<Response<Body>, MmProxyError> {
+        let (parts, mut json) = monerod_resp.into_parts();
+        let blob = match json["result"]["blob"].as_str() {
+            Some(blob) => blob.to_string(),
+            None => return Ok(proxy::into_response(parts, &json)),
+        };
+
+        match self.verify_merge_mining_tag(&blob).await {
+            Ok(()) => {
+                json["result"]["tari_mm_verified"] = json!(true);
+            },
+            Err(reason) => {
+                warn!(target: LOG_TARGET, "Merge mining tag verification failed: {}", reason);
+                json["result"]["tari_mm_verified"] = json!(false);
+                json["result"]["tari_mm_error"] = json!(reason);
+            },
+        }
+
+        Ok(proxy::into_response(parts, &json))
+    }
+
+    /// Checks that the hex encoded Monero block has a well-formed Tari merge mining tag for a block template built by
+    /// this proxy. Returns a description of the problem if it does not.
+    async fn verify_merge_mining_tag(&self, blob: &str) -> Result<(), String> {
+        let monero_block = merge_mining::deserialize_monero_block_from_hex(blob).map_err(|err| err.to_string())?;
+        let hash = merge_mining::check_merge_mining_tag(&monero_block)?;
+        if self.block_templates.get(&hash).await.is_none() {
+            return Err(format!(
+                "Merge mining hash {} does not match a known block template",
+                hex::encode(&hash)
+            ));
+        }
+        Ok(())
+    }
+
     async fn handle_get_last_block_header(
         &self,
         monero_resp: Response<json::Value>,
@@ -745,14 +1306,7 @@ impl InnerService {
         Ok(uri)
     }
 
-    /// Proxy a request received by this server to Monerod
-    async fn proxy_request_to_monerod(
-        &self,
-        request: Request<Bytes>,
-    ) -> Result<(Request<Bytes>, Response<json::Value>), MmProxyError>
-    {
-        let monerod_uri = self.get_fully_qualified_monerod_url(request.uri())?;
-
+    fn monerod_request_builder(&self, request: &Request<Bytes>, monerod_uri: &Url) -> reqwest::RequestBuilder {
         let mut builder = self
             .http_client
             .request(request.method().clone(), monerod_uri.clone())
@@ -767,11 +1321,46 @@ impl InnerService {
             builder = builder.header(header::HOST, host);
         }
 
-        if self.config.monerod_use_auth {
+        if self.config.monerod_use_auth && self.config.monerod_auth_scheme == MonerodAuthScheme::Basic {
             // Use HTTP basic auth. This is the only reason we are using `reqwest` over the standard hyper client.
             builder = builder.basic_auth(&self.config.monerod_username, Some(&self.config.monerod_password));
         }
 
+        builder
+    }
+
+    /// Computes the digest `Authorization` header value in response to the challenge(s) in a monerod 401 response
+    fn digest_authorization(
+        &self,
+        request: &Request<Bytes>,
+        monerod_uri: &Url,
+        headers: &header::HeaderMap,
+    ) -> Result<String, MmProxyError>
+    {
+        let challenge = DigestChallenge::from_headers(headers)?;
+        let uri = match monerod_uri.query() {
+            Some(query) => format!("{}?{}", monerod_uri.path(), query),
+            None => monerod_uri.path().to_string(),
+        };
+        let cnonce = format!("{:016x}", rand::random::<u64>());
+        Ok(challenge.authorization(
+            &self.config.monerod_username,
+            &self.config.monerod_password,
+            request.method().as_str(),
+            &uri,
+            1,
+            &cnonce,
+        ))
+    }
+
+    /// Proxy a request received by this server to Monerod
+    async fn proxy_request_to_monerod(
+        &self,
+        request: Request<Bytes>,
+    ) -> Result<(Request<Bytes>, Response<json::Value>), MmProxyError>
+    {
+        let monerod_uri = self.get_fully_qualified_monerod_url(request.uri())?;
+
         debug!(
             target: LOG_TARGET,
             "[monerod] request: {} {}",
@@ -780,8 +1369,7 @@ impl InnerService {
         );
 
         let mut submit_block = false;
-        let body: Bytes = request.body().clone();
-        let json = json::from_slice::<json::Value>(&body[..]).unwrap_or_default();
+        let json = json::from_slice::<json::Value>(request.body()).unwrap_or_default();
         if let Some(method) = json["method"].as_str() {
             match method {
                 "submitblock" | "submit_block" => {
@@ -817,17 +1405,13 @@ impl InnerService {
             // very much against spamming the nodes unnecessarily.
             // NB!: This is by design, do not change this without understanding
             // it's implications.
-            let accept_response = json_rpc::default_block_accept_response(json["id"].as_i64());
+            let accept_response = json_rpc::default_block_accept_response(json["id"].clone());
             json_response =
                 convert_json_to_hyper_json_response(accept_response, StatusCode::OK, monerod_uri.clone()).await?;
         } else {
-            let resp = builder
-                // This is a cheap clone of the request body
-                .body(body)
-                .send()
-                .await
-                .map_err(MmProxyError::MonerodRequestFailed)?;
-            json_response = convert_reqwest_response_to_hyper_json_response(resp).await?
+            let resp = self.send_to_monerod(&request, &monerod_uri).await?;
+            json_response =
+                convert_reqwest_response_to_hyper_json_response(resp, self.config.monerod_validate_content_type).await?
         };
 
         let rpc_status = if json_response.body()["error"].is_null() {
@@ -846,13 +1430,92 @@ impl InnerService {
         Ok((request, json_response))
     }
 
+    /// Sends the request to monerod, answering a digest authentication challenge if necessary
+    async fn send_to_monerod(
+        &self,
+        request: &Request<Bytes>,
+        monerod_uri: &Url,
+    ) -> Result<reqwest::Response, MmProxyError>
+    {
+        let start = Instant::now();
+        let mut resp = self
+            .monerod_request_builder(request, monerod_uri)
+            // This is a cheap clone of the request body
+            .body(request.body().clone())
+            .send()
+            .await
+            .map_err(MmProxyError::MonerodRequestFailed)?;
+        if resp.status() == StatusCode::UNAUTHORIZED &&
+            self.config.monerod_use_auth &&
+            self.config.monerod_auth_scheme == MonerodAuthScheme::Digest
+        {
+            let authorization = self.digest_authorization(request, monerod_uri, resp.headers())?;
+            resp = self
+                .monerod_request_builder(request, monerod_uri)
+                .header(header::AUTHORIZATION, authorization)
+                .body(request.body().clone())
+                .send()
+                .await
+                .map_err(MmProxyError::MonerodRequestFailed)?;
+        }
+        self.stats.record_monerod_latency(start.elapsed());
+        Ok(resp)
+    }
+
+    /// Returns true if the monerod response to `request` has to be parsed, because the proxy modifies it or reads
+    /// data from it
+    fn is_response_inspected(&self, request: &Request<Bytes>) -> bool {
+        if !self.response_transforms.is_empty() {
+            return true;
+        }
+        match *request.method() {
+            Method::GET => matches!(request.uri().path(), "/get_height" | "/getheight"),
+            Method::POST => {
+                let json = json::from_slice::<json::Value>(request.body()).unwrap_or_default();
+                match json["method"].as_str().unwrap_or_default() {
+                    "submitblock" |
+                    "submit_block" |
+                    "getblocktemplate" |
+                    "get_block_template" |
+                    "getblockheaderbyhash" |
+                    "get_block_header_by_hash" |
+                    "getlastblockheader" |
+                    "get_last_block_header" => true,
+                    "getblock" | "get_block" => self.config.proxy_verify_get_block,
+                    _ => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Forwards the request to monerod and streams the response back without parsing it
+    async fn proxy_passthrough_to_monerod(&self, request: Request<Bytes>) -> Result<Response<Body>, MmProxyError> {
+        let monerod_uri = self.get_fully_qualified_monerod_url(request.uri())?;
+        debug!(
+            target: LOG_TARGET,
+            "[monerod] passthrough request: {} {}",
+            request.method(),
+            monerod_uri,
+        );
+        let resp = self.send_to_monerod(&request, &monerod_uri).await?;
+
+        let mut builder = Response::builder().status(resp.status()).version(resp.version());
+        let headers = builder
+            .headers_mut()
+            .expect("headers_mut errors only when the builder has an error (e.g invalid header value)");
+        headers.extend(resp.headers().iter().map(|(name, value)| (name.clone(), value.clone())));
+        Ok(builder.body(Body::wrap_stream(resp.bytes_stream()))?)
+    }
+
     async fn get_proxy_response(
         &self,
         request: Request<Bytes>,
         monerod_resp: Response<json::Value>,
     ) -> Result<Response<Body>, MmProxyError>
     {
-        match request.method().clone() {
+        let path = request.uri().path().to_string();
+        let response = match request.method().clone() {
             Method::GET => {
                 // All get requests go to /request_name, methods do not have a body, optionally could have query params
                 // if applicable.
@@ -868,26 +1531,97 @@ impl InnerService {
                 let request = request.map(move |_| json);
                 match request.body()["method"].as_str().unwrap_or_default() {
                     "submitblock" | "submit_block" => self.handle_submit_block(request, monerod_resp).await,
-                    "getblocktemplate" | "get_block_template" => self.handle_get_block_template(monerod_resp).await,
+                    "getblocktemplate" | "get_block_template" => {
+                        self.handle_get_block_template(request, monerod_resp).await
+                    },
                     "getblockheaderbyhash" | "get_block_header_by_hash" => {
                         self.handle_get_block_header_by_hash(request, monerod_resp).await
                     },
                     "getlastblockheader" | "get_last_block_header" => {
                         self.handle_get_last_block_header(monerod_resp).await
                     },
+                    "getblock" | "get_block" if self.config.proxy_verify_get_block => {
+                        self.handle_get_block(monerod_resp).await
+                    },
 
                     _ => Ok(proxy::into_body_from_response(monerod_resp)),
                 }
             },
             // Simply return the response "as is"
             _ => Ok(proxy::into_body_from_response(monerod_resp)),
+        }?;
+
+        self.apply_response_transforms(&path, response).await
+    }
+
+    async fn apply_response_transforms(
+        &self,
+        path: &str,
+        mut response: Response<Body>,
+    ) -> Result<Response<Body>, MmProxyError>
+    {
+        if self.response_transforms.is_empty() {
+            return Ok(response);
+        }
+
+        let bytes = proxy::read_body_until_end(response.body_mut()).await?;
+        let (parts, _) = response.into_parts();
+        let mut json = match json::from_slice::<json::Value>(&bytes) {
+            Ok(json) => json,
+            // Bodies that are not JSON (e.g. an unchanged block template response) are returned as is
+            Err(_) => return Ok(Response::from_parts(parts, bytes.freeze().into())),
+        };
+        for transform in &self.response_transforms {
+            transform.transform(path, &mut json);
+        }
+        Ok(proxy::into_response(parts, &json))
+    }
+
+    /// Handles requests to the proxy's own `/debug/...` endpoints. Returns None if the request is not for a debug
+    /// endpoint or if debug endpoints are disabled.
+    fn handle_debug_request(&self, request: &Request<Bytes>) -> Option<Result<Response<Body>, MmProxyError>> {
+        if !self.config.proxy_enable_debug_endpoints || request.method() != Method::GET {
+            return None;
+        }
+
+        match request.uri().path() {
+            "/debug/config" => Some(proxy::json_response(StatusCode::OK, &self.config.to_redacted_json())),
+            _ => None,
         }
     }
 
     async fn handle(self, mut request: Request<Body>) -> Result<Response<Body>, MmProxyError> {
         let start = Instant::now();
+        if !self.config.is_client_allowed(self.remote_addr) {
+            warn!(
+                target: LOG_TARGET,
+                "Rejected request from client address {:?} that is not in the allowlist", self.remote_addr
+            );
+            return proxy::json_response(
+                StatusCode::FORBIDDEN,
+                &json!({ "error": "Client address is not allowed to use this proxy" }),
+            );
+        }
         let bytes = proxy::read_body_until_end(request.body_mut()).await?;
         let request = request.map(|_| bytes.freeze());
+        if let Some(resp) = self.handle_debug_request(&request) {
+            return resp;
+        }
+        if request.method() == Method::GET && request.uri().path() == "/stats" {
+            return proxy::json_response(StatusCode::OK, &self.stats.to_json());
+        }
+        if request.method() == Method::GET && request.uri().path() == "/difficulty_history" {
+            return proxy::json_response(StatusCode::OK, &self.difficulty_history.to_json());
+        }
+        if request.method() == Method::GET && request.uri().path() == "/ready" {
+            let is_ready = self.readiness.as_ref().map(ReadinessGate::is_ready).unwrap_or(true);
+            let status = if is_ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            return proxy::json_response(status, &json!({ "ready": is_ready }));
+        }
         let method_name;
         match *request.method() {
             Method::GET => {
@@ -904,6 +1638,36 @@ impl InnerService {
             },
         }
 
+        let is_submit_block = method_name == "submitblock" || method_name == "submit_block";
+        if self.draining.load(Ordering::SeqCst) && !is_submit_block {
+            debug!(
+                target: LOG_TARGET,
+                "Proxy is shutting down, refusing `{}` request", method_name
+            );
+            return proxy::json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &json!({ "error": "The proxy is shutting down" }),
+            );
+        }
+        if let Some(ref readiness) = self.readiness {
+            if !readiness.is_ready() {
+                debug!(
+                    target: LOG_TARGET,
+                    "Proxy is warming up, deferring `{}` request", method_name
+                );
+                if time::timeout(self.config.proxy_warm_up_wait_timeout, readiness.wait())
+                    .await
+                    .is_err()
+                {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Proxy did not warm up in time, refusing `{}` request", method_name
+                    );
+                    return proxy::json_response(StatusCode::SERVICE_UNAVAILABLE, &json!({ "ready": false }));
+                }
+            }
+        }
+
         debug!(
             target: LOG_TARGET,
             "request: {} ({})",
@@ -916,6 +1680,17 @@ impl InnerService {
                 .join(","),
         );
 
+        if self.config.proxy_passthrough_unhandled_responses && !self.is_response_inspected(&request) {
+            let response = self.proxy_passthrough_to_monerod(request).await?;
+            println!(
+                "Method: {}, MoneroD Status: {}, Proxy Status: passthrough, Response Time: {}ms",
+                method_name,
+                response.status(),
+                start.elapsed().as_millis()
+            );
+            return Ok(response);
+        }
+
         let (request, monerod_resp) = self.proxy_request_to_monerod(request).await?;
         // Any failed (!= 200 OK) responses from Monero are immediately returned to the requester
         let monerod_status = monerod_resp.status();
@@ -947,9 +1722,29 @@ impl InnerService {
     }
 }
 
+/// The maximum number of characters of an unexpected response body that is included in the error
+const UNEXPECTED_BODY_SNIPPET_LEN: usize = 200;
+
 async fn convert_reqwest_response_to_hyper_json_response(
     resp: reqwest::Response,
-) -> Result<Response<json::Value>, MmProxyError> {
+    validate_content_type: bool,
+) -> Result<Response<json::Value>, MmProxyError>
+{
+    if validate_content_type {
+        if let Some(content_type) = resp.headers().get(header::CONTENT_TYPE) {
+            let content_type = String::from_utf8_lossy(content_type.as_bytes()).to_string();
+            if !is_json_content_type(&content_type) {
+                let status = resp.status();
+                let body = resp.text().await.unwrap_or_default();
+                return Err(MmProxyError::UnexpectedContentType {
+                    got: content_type,
+                    status,
+                    body_snippet: body.chars().take(UNEXPECTED_BODY_SNIPPET_LEN).collect(),
+                });
+            }
+        }
+    }
+
     let mut builder = Response::builder();
 
     let headers = builder
@@ -967,6 +1762,107 @@ async fn convert_reqwest_response_to_hyper_json_response(
     Ok(resp)
 }
 
+async fn submit_block_to_base_node(
+    address: SocketAddr,
+    block: grpc::Block,
+) -> Result<grpc::SubmitBlockResponse, MmProxyError>
+{
+    let mut client = grpc::base_node_client::BaseNodeClient::connect(format!("http://{}", address)).await?;
+    let resp = client.submit_block(block).await?;
+    Ok(resp.into_inner())
+}
+
+/// Selects the outcome of submitting a block to several base nodes, in submission order. The first accepted submission
+/// is returned, otherwise the error from the first base node. A submission that timed out counts as an error.
+pub(crate) fn select_block_submission_result(
+    results: Vec<(SocketAddr, Result<grpc::SubmitBlockResponse, MmProxyError>)>,
+) -> Result<grpc::SubmitBlockResponse, MmProxyError> {
+    let mut first_err = None;
+    for (_, result) in results {
+        match result {
+            Ok(resp) => return Ok(resp),
+            Err(err) => {
+                if first_err.is_none() {
+                    first_err = Some(err);
+                }
+            },
+        }
+    }
+    Err(first_err.unwrap_or_else(|| MmProxyError::MissingDataError("No base node to submit the block to".to_string())))
+}
+
+/// Returns the entity tag that identifies a block template. A template is considered unchanged while the Tari height,
+/// Monero height and RandomX seed are unchanged.
+pub(crate) fn block_template_id(tari_height: u64, monero_height: u64, seed_hash: &str) -> String {
+    format!("\"{}-{}-{}\"", tari_height, monero_height, seed_hash)
+}
+
+/// Returns true if the `If-None-Match` header value contains the given template id
+pub(crate) fn is_template_id_match(if_none_match: &HeaderValue, template_id: &str) -> bool {
+    if_none_match
+        .to_str()
+        .map(|v| v.split(',').any(|tag| tag.trim() == template_id))
+        .unwrap_or(false)
+}
+
+/// Parses the `proxy_pow_algo` setting. Only Monero blocks can be merge mined.
+pub(crate) fn parse_pow_algo(value: &str) -> Result<PowAlgorithm, ConfigurationError> {
+    match value {
+        "Monero" => Ok(PowAlgorithm::Monero),
+        other => Err(ConfigurationError::new(
+            "merge_mining_proxy.proxy_pow_algo",
+            &format!(
+                "unsupported PoW algorithm `{}`, only `Monero` can be merge mined",
+                other
+            ),
+        )),
+    }
+}
+
+/// Returns an error if the base node returned a block template for a different PoW algorithm than the one requested
+pub(crate) fn check_template_pow_algo(
+    template: &grpc::NewBlockTemplate,
+    expected: PowAlgorithm,
+) -> Result<(), MmProxyError>
+{
+    let got = template
+        .header
+        .as_ref()
+        .and_then(|header| header.pow.as_ref())
+        .map(|pow| pow.pow_algo)
+        .ok_or_else(|| MmProxyError::GrpcResponseMissingField("new_block_template.header.pow"))?;
+    if got != expected.as_u64() {
+        return Err(MmProxyError::UnexpectedPowAlgorithm { expected, got });
+    }
+    Ok(())
+}
+
+/// Returns an error if a monerod JSON-RPC response contains an `error` object
+pub(crate) fn check_monerod_rpc_error(resp: &json::Value) -> Result<(), MmProxyError> {
+    let error = &resp["error"];
+    if error.is_null() {
+        return Ok(());
+    }
+    Err(MmProxyError::MonerodRpcError {
+        code: error["code"].as_i64().unwrap_or_default(),
+        message: error["message"]
+            .as_str()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| error.to_string()),
+    })
+}
+
+/// Returns true if the media type is `application/json` or a `+json` structured syntax suffix type
+fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    media_type == "application/json" || media_type.ends_with("+json")
+}
+
 /// Add mmproxy extensions object to JSON RPC success response
 pub fn add_aux_data(mut response: json::Value, mut ext: json::Value) -> json::Value {
     if response["result"].is_null() {
@@ -1008,6 +1904,50 @@ pub fn append_aux_chain_data(mut response: json::Value, chain_data: json::Value)
     response
 }
 
+/// Returns the block template to serve when the coinbase could not be added to a new Tari block template, according to
+/// the given policy. The last block template is served, marked as stale and with the id of the current request, if the
+/// policy allows it and one is available that was built on the same Monero tip and seed as the fresh `monerod_resp`.
+/// Otherwise the coinbase error is returned.
+pub(crate) fn fallback_block_template(
+    policy: CoinbaseFailurePolicy,
+    request_id: json::Value,
+    monerod_resp: &json::Value,
+    last_block_template: Option<json::Value>,
+    err: MmProxyError,
+) -> Result<json::Value, MmProxyError>
+{
+    match (policy, last_block_template) {
+        (CoinbaseFailurePolicy::ServeLastTemplate, Some(last_block_template))
+            if is_same_monero_template_base(&last_block_template, monerod_resp) =>
+        {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to add coinbase to new block template ({}). Serving last block template.", err
+            );
+            let mut resp = add_aux_data(last_block_template, json!({ "stale": true }));
+            resp["id"] = request_id;
+            Ok(resp)
+        }
+        (CoinbaseFailurePolicy::ServeLastTemplate, Some(_)) => {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to add coinbase to new block template ({}). The last block template was built on a different \
+                 Monero tip or seed and cannot be served.",
+                err
+            );
+            Err(err)
+        },
+        _ => Err(err),
+    }
+}
+
+/// Returns true if both `get_block_template` results build on the same Monero block with the same RandomX seed
+fn is_same_monero_template_base(a: &json::Value, b: &json::Value) -> bool {
+    ["height", "prev_hash", "seed_hash"]
+        .iter()
+        .all(|field| a["result"][field] == b["result"][field])
+}
+
 fn try_into_json_block_header(header: grpc::BlockHeaderResponse) -> Result<json::Value, MmProxyError> {
     let grpc::BlockHeaderResponse {
         header,
@@ -0,0 +1,81 @@
+//  Copyright 2021, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use serde_json as json;
+use std::{
+    fmt,
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+/// A hook that can modify the JSON body of every response the proxy returns to the miner. Registered transforms are
+/// applied in registration order after the proxy has added its own data to the response.
+pub trait ResponseTransform: Debug + Send + Sync {
+    /// Modify `json`, the response to a request for `path` (e.g. `/json_rpc` or `/get_height`)
+    fn transform(&self, path: &str, json: &mut json::Value);
+}
+
+/// Sets a string field, given by a JSON pointer (e.g. `/result/pool_note`), on every response. The field is only set if
+/// its parent object exists in the response; an existing value is replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetResponseField {
+    pointer: String,
+    value: String,
+}
+
+impl ResponseTransform for SetResponseField {
+    fn transform(&self, _: &str, json: &mut json::Value) {
+        let (parent, name) = self
+            .pointer
+            .split_at(self.pointer.rfind('/').expect("checked in from_str"));
+        if let Some(obj) = json.pointer_mut(parent).and_then(json::Value::as_object_mut) {
+            let name = name[1..].replace("~1", "/").replace("~0", "~");
+            obj.insert(name, self.value.clone().into());
+        }
+    }
+}
+
+impl FromStr for SetResponseField {
+    type Err = String;
+
+    /// Parses a `<JSON pointer>=<string value>` setting
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let pointer = parts.next().unwrap_or_default();
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("`{}` is not of the form <JSON pointer>=<value>", s))?;
+        if !pointer.starts_with('/') || pointer.ends_with('/') {
+            return Err(format!("`{}` is not a JSON pointer to a field", pointer));
+        }
+        Ok(Self {
+            pointer: pointer.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl Display for SetResponseField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.pointer, self.value)
+    }
+}
@@ -0,0 +1,189 @@
+//  Copyright 2021, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use chrono::{DateTime, Utc};
+use serde_json as json;
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// The number of most recent monerod round trip times used to calculate latency percentiles
+const MAX_LATENCY_SAMPLES: usize = 1000;
+
+/// Mining statistics collected by the proxy, served as JSON on the `/stats` endpoint
+#[derive(Debug)]
+pub struct ProxyStats {
+    started_at: Instant,
+    tari_height: AtomicU64,
+    monero_height: AtomicU64,
+    blocks_submitted: AtomicU64,
+    blocks_accepted: AtomicU64,
+    blocks_rejected: AtomicU64,
+    monerod_latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl ProxyStats {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            tari_height: AtomicU64::new(0),
+            monero_height: AtomicU64::new(0),
+            blocks_submitted: AtomicU64::new(0),
+            blocks_accepted: AtomicU64::new(0),
+            blocks_rejected: AtomicU64::new(0),
+            monerod_latencies: Mutex::new(VecDeque::with_capacity(MAX_LATENCY_SAMPLES)),
+        }
+    }
+
+    pub fn set_tari_height(&self, height: u64) {
+        self.tari_height.store(height, Ordering::Relaxed);
+    }
+
+    pub fn set_monero_height(&self, height: u64) {
+        self.monero_height.store(height, Ordering::Relaxed);
+    }
+
+    /// Records the result of submitting a block to the Tari base node
+    pub fn record_submission(&self, is_accepted: bool) {
+        self.blocks_submitted.fetch_add(1, Ordering::Relaxed);
+        if is_accepted {
+            self.blocks_accepted.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.blocks_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the round trip time of a request to monerod, discarding the oldest sample once
+    /// `MAX_LATENCY_SAMPLES` have been recorded
+    pub fn record_monerod_latency(&self, latency: Duration) {
+        let mut latencies = self.monerod_latencies.lock().unwrap();
+        if latencies.len() == MAX_LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    pub fn to_json(&self) -> json::Value {
+        let mut latencies = self
+            .monerod_latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        latencies.sort();
+        json!({
+            "tari_height": self.tari_height.load(Ordering::Relaxed),
+            "monero_height": self.monero_height.load(Ordering::Relaxed),
+            "blocks_submitted": self.blocks_submitted.load(Ordering::Relaxed),
+            "blocks_accepted": self.blocks_accepted.load(Ordering::Relaxed),
+            "blocks_rejected": self.blocks_rejected.load(Ordering::Relaxed),
+            "uptime_secs": self.started_at.elapsed().as_secs(),
+            "monerod_latency_ms": {
+                "samples": latencies.len(),
+                "p50": percentile(&latencies, 50),
+                "p90": percentile(&latencies, 90),
+                "p99": percentile(&latencies, 99),
+            },
+        })
+    }
+}
+
+impl Default for ProxyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The Tari target difficulty of recently built block templates, served as JSON on the `/difficulty_history` endpoint.
+/// Only the most recent `capacity` entries are kept.
+#[derive(Debug)]
+pub struct DifficultyHistory {
+    capacity: usize,
+    entries: Mutex<VecDeque<DifficultyEntry>>,
+}
+
+#[derive(Debug, Clone)]
+struct DifficultyEntry {
+    timestamp: DateTime<Utc>,
+    height: u64,
+    difficulty: u64,
+}
+
+impl DifficultyHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            // The history fills up as block templates are built, so memory is not reserved for a large capacity upfront
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records the target difficulty of a block template built for `height`, discarding the oldest entry once
+    /// `capacity` entries have been recorded
+    pub fn record(&self, height: u64, difficulty: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(DifficultyEntry {
+            timestamp: Utc::now(),
+            height,
+            difficulty,
+        });
+    }
+
+    /// Returns the recorded entries, oldest first
+    pub fn to_json(&self) -> json::Value {
+        let entries = self.entries.lock().unwrap();
+        json::Value::Array(
+            entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "timestamp": entry.timestamp.timestamp(),
+                        "height": entry.height,
+                        "difficulty": entry.difficulty,
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Returns the `p`th percentile in milliseconds of the given sorted samples, or None if there are no samples
+fn percentile(sorted: &[Duration], p: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = (sorted.len() - 1) * p / 100;
+    Some(sorted[index].as_millis() as u64)
+}
@@ -0,0 +1,81 @@
+//  Copyright 2021, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use futures::{future, Future};
+use std::time::Duration;
+use tari_shutdown::{Shutdown, ShutdownSignal};
+use tokio::{task, task::JoinHandle, time};
+use tracing::{debug, warn};
+
+const LOG_TARGET: &str = "tari_mm_proxy::task_set";
+
+/// Owns the long-running tasks of the proxy so that they can be stopped together. Tasks obtain a `ShutdownSignal` from
+/// the set and are expected to exit once it resolves.
+pub struct ProxyTaskSet {
+    shutdown: Shutdown,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl ProxyTaskSet {
+    pub fn new() -> Self {
+        Self {
+            shutdown: Shutdown::new(),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Returns a signal that resolves when `shutdown` is called
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.shutdown.to_signal()
+    }
+
+    /// Spawns a task and registers it with the set
+    pub fn spawn<F>(&mut self, name: &'static str, task: F)
+    where F: Future<Output = ()> + Send + 'static {
+        debug!(target: LOG_TARGET, "Spawning task `{}`", name);
+        self.tasks.push((name, task::spawn(task)));
+    }
+
+    /// Signals all tasks to shut down and waits up to `timeout` for them to finish. Returns the names of the tasks that
+    /// did not stop in time.
+    pub async fn shutdown(mut self, timeout: Duration) -> Vec<&'static str> {
+        let _ = self.shutdown.trigger();
+        let (names, handles): (Vec<_>, Vec<_>) = self.tasks.into_iter().unzip();
+        let results = future::join_all(handles.into_iter().map(|handle| time::timeout(timeout, handle))).await;
+
+        let mut not_stopped = Vec::new();
+        for (name, result) in names.into_iter().zip(results) {
+            match result {
+                Ok(Ok(())) => debug!(target: LOG_TARGET, "Task `{}` stopped", name),
+                Ok(Err(err)) => warn!(target: LOG_TARGET, "Task `{}` failed: {}", name, err),
+                Err(_) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Task `{}` did not stop within {:.0?}", name, timeout
+                    );
+                    not_stopped.push(name);
+                },
+            }
+        }
+        not_stopped
+    }
+}
@@ -20,9 +20,21 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{common::proxy, proxy::MergeMiningProxyConfig};
-use hyper::Body;
+use crate::{
+    common::proxy,
+    proxy::{CoinbaseFailurePolicy, MergeMiningProxyConfig, MonerodAuthScheme},
+};
+use futures::Future;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body,
+    Request,
+    Response,
+    Server,
+};
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
 use tari_common::Network;
+use tari_core::proof_of_work::PowAlgorithm;
 
 fn default_test_config() -> MergeMiningProxyConfig {
     MergeMiningProxyConfig {
@@ -31,10 +43,28 @@ fn default_test_config() -> MergeMiningProxyConfig {
         monerod_username: "".to_string(),
         monerod_password: "".to_string(),
         monerod_use_auth: false,
+        monerod_auth_scheme: MonerodAuthScheme::Basic,
+        monerod_validate_content_type: true,
         grpc_base_node_address: "127.0.0.1:9999".parse().unwrap(),
         grpc_console_wallet_address: "127.0.0.1:9998".parse().unwrap(),
         proxy_host_address: "127.0.0.1:9997".parse().unwrap(),
         proxy_submit_to_origin: false,
+        proxy_enable_debug_endpoints: false,
+        coinbase_failure_policy: CoinbaseFailurePolicy::Fail,
+        proxy_report_stale_submissions: false,
+        proxy_monero_seed_max_age: Duration::from_secs(600),
+        allowed_client_cidrs: Vec::new(),
+        pow_algo: PowAlgorithm::Monero,
+        proxy_verify_get_block: false,
+        proxy_drain_timeout: Duration::from_secs(30),
+        proxy_warm_up_wait_timeout: Duration::from_secs(30),
+        proxy_difficulty_history_size: 1000,
+        block_templates_file: None,
+        proxy_passthrough_unhandled_responses: false,
+        proxy_mirror_submit_base_node_addresses: Vec::new(),
+        proxy_tls_identity_file: None,
+        proxy_tls_identity_password: String::new(),
+        proxy_response_fields: Vec::new(),
         wait_for_initial_sync_at_startup: true,
     }
 }
@@ -43,12 +73,28 @@ async fn read_body_as_json(body: &mut Body) -> serde_json::Value {
     serde_json::from_slice(&proxy::read_body_until_end(body).await.unwrap()).unwrap()
 }
 
+/// Starts a server on a random local port that responds to every request using `handler`
+fn start_simulated_monerod<F, Fut>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<Response<Body>, Infallible>> + Send + 'static,
+{
+    let make_service = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move { Ok::<_, Infallible>(service_fn(handler)) }
+    });
+    let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
 mod merge_mining_proxy_service {
     use super::*;
     use crate::{block_template_data::BlockTemplateRepository, proxy::MergeMiningProxyService};
     use futures::task::Poll;
     use futures_test::task::noop_context;
-    use hyper::{service::Service, Body, Request};
+    use hyper::{service::Service, Body, Request, StatusCode};
 
     #[test]
     fn it_is_always_ready() {
@@ -70,25 +116,1270 @@ mod merge_mining_proxy_service {
         let json = read_body_as_json(resp.body_mut()).await;
         assert_eq!(json["error"]["message"], "Internal error");
     }
+
+    #[tokio_macros::test]
+    async fn it_returns_the_redacted_config_if_debug_endpoints_are_enabled() {
+        let mut config = default_test_config();
+        config.monerod_password = "hunter2".to_string();
+        config.proxy_enable_debug_endpoints = true;
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+        let req = Request::get("/debug/config").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["monerod_password"], MergeMiningProxyConfig::REDACTED);
+        assert_eq!(json["proxy_host_address"], "127.0.0.1:9997");
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_serve_debug_endpoints_if_disabled() {
+        let mut service = MergeMiningProxyService::new(default_test_config(), BlockTemplateRepository::new());
+        let req = Request::get("/debug/config").body(Body::empty()).unwrap();
+        let resp = service.call(req).await.unwrap();
+        assert_eq!(resp.status().is_success(), false);
+    }
+
+    #[tokio_macros::test]
+    async fn it_rejects_clients_outside_the_allowlist() {
+        let mut config = default_test_config();
+        config.proxy_enable_debug_endpoints = true;
+        config.allowed_client_cidrs = vec!["192.168.1.0/24".parse().unwrap()];
+        let service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/debug/config").body(Body::empty()).unwrap();
+        let resp = service
+            .with_remote_addr("10.0.0.1:5000".parse().unwrap())
+            .call(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = Request::get("/debug/config").body(Body::empty()).unwrap();
+        let resp = service
+            .with_remote_addr("192.168.1.20:5000".parse().unwrap())
+            .call(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
+
+mod monerod_digest_auth {
+    use super::*;
+    use crate::{
+        block_template_data::BlockTemplateRepository,
+        common::digest_auth::DigestChallenge,
+        proxy::MergeMiningProxyService,
+    };
+    use hyper::{header, service::Service, StatusCode};
+    use std::convert::TryFrom;
+
+    const CHALLENGE: &str = r#"Digest qop="auth",algorithm=MD5,realm="monero-rpc",nonce="1f2e3d4c5b6a",stale=false"#;
+    const CHALLENGE_MD5_SESS: &str =
+        r#"Digest qop="auth",algorithm=MD5-sess,realm="monero-rpc",nonce="1f2e3d4c5b6a",stale=false"#;
+
+    /// Responds with 401 unless the request carries a valid digest `Authorization` header for user:hunter2
+    async fn simulated_monerod(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let is_authorized = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(|auth| {
+                let cnonce = auth
+                    .split("cnonce=\"")
+                    .nth(1)
+                    .and_then(|s| s.split('"').next())
+                    .unwrap_or_default();
+                let expected = DigestChallenge::parse(CHALLENGE).unwrap().authorization(
+                    "user",
+                    "hunter2",
+                    req.method().as_str(),
+                    req.uri().path(),
+                    1,
+                    cnonce,
+                );
+                auth == expected
+            })
+            .unwrap_or(false);
+
+        let resp = if is_authorized {
+            Response::new(Body::from(r#"{"status":"OK"}"#))
+        } else {
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, CHALLENGE_MD5_SESS)
+                .header(header::WWW_AUTHENTICATE, CHALLENGE)
+                .body(Body::empty())
+                .unwrap()
+        };
+        Ok(resp)
+    }
+
+    #[test]
+    fn it_computes_the_rfc2617_example_response() {
+        let challenge = DigestChallenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap();
+        assert_eq!(challenge.realm, "testrealm@host.com");
+        assert!(challenge.qop_auth);
+        let auth = challenge.authorization("Mufasa", "Circle Of Life", "GET", "/dir/index.html", 1, "0a4f113b");
+        assert!(auth.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(auth.contains("nc=00000001"));
+        assert!(auth.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+    }
+
+    #[test]
+    fn it_rejects_unsupported_challenges() {
+        assert!(DigestChallenge::parse(CHALLENGE_MD5_SESS).is_err());
+        assert!(DigestChallenge::parse(r#"Basic realm="monero-rpc""#).is_err());
+        assert!(DigestChallenge::parse(r#"Digest nonce="abc""#).is_err());
+    }
+
+    #[test]
+    fn it_selects_the_first_supported_challenge() {
+        let mut headers = header::HeaderMap::new();
+        headers.append(header::WWW_AUTHENTICATE, CHALLENGE_MD5_SESS.parse().unwrap());
+        headers.append(header::WWW_AUTHENTICATE, CHALLENGE.parse().unwrap());
+        let challenge = DigestChallenge::from_headers(&headers).unwrap();
+        assert_eq!(challenge, DigestChallenge::parse(CHALLENGE).unwrap());
+        assert!(DigestChallenge::from_headers(&header::HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn it_parses_the_auth_scheme_from_config() {
+        assert_eq!(
+            MonerodAuthScheme::try_from("Basic".to_string()).unwrap(),
+            MonerodAuthScheme::Basic
+        );
+        assert_eq!(
+            MonerodAuthScheme::try_from("Digest".to_string()).unwrap(),
+            MonerodAuthScheme::Digest
+        );
+        assert!(MonerodAuthScheme::try_from("digest".to_string()).is_err());
+    }
+
+    #[tokio_macros::test]
+    async fn it_retries_with_digest_auth_after_a_challenge() {
+        let addr = start_simulated_monerod(simulated_monerod);
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        config.monerod_use_auth = true;
+        config.monerod_auth_scheme = MonerodAuthScheme::Digest;
+        config.monerod_username = "user".to_string();
+        config.monerod_password = "hunter2".to_string();
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["status"], "OK");
+    }
+}
+
+mod submit_block {
+    use super::*;
+    use crate::{
+        block_template_data::{BlockTemplateDataBuilder, BlockTemplateRepository},
+        common::merge_mining,
+        proxy::MergeMiningProxyService,
+    };
+    use hyper::{service::Service, Request, StatusCode};
+    use serde_json::json;
+    use tari_app_grpc::tari_rpc as grpc;
+    use tari_core::proof_of_work::monero_rx;
+
+    const BLOCKTEMPLATE_BLOB: &str = "0c0c8cd6a0fa057fe21d764e7abf004e975396a2160773b93712bf6118c3b4959ddd8ee0f76aad0000000002e1ea2701ffa5ea2701d5a299e2abb002028eb3066ced1b2cc82ea046f3716a48e9ae37144057d5fb48a97f941225a1957b2b0106225b7ec0a6544d8da39abe68d8bd82619b4a7c5bdae89c3783b256a8fa47820208f63aa86d2e857f070000";
+
+    /// A submit_block request for a block whose Tari merge mining hash has no template in the repository
+    fn submit_block_request_for_unknown_template() -> Request<Body> {
+        let mut block = merge_mining::deserialize_monero_block_from_hex(BLOCKTEMPLATE_BLOB).unwrap();
+        monero_rx::append_merge_mining_tag(&mut block, [1u8; 32]).unwrap();
+        let blob = merge_mining::serialize_monero_block_to_hex(&block).unwrap();
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": "submit_block", "params": [blob]});
+        Request::post("/json_rpc").body(body.to_string().into()).unwrap()
+    }
+
+    fn test_config() -> MergeMiningProxyConfig {
+        let mut config = default_test_config();
+        // Not contacted in self-select mode, but the URL must be valid
+        config.monerod_url = "http://127.0.0.1:18081".to_string();
+        config
+    }
+
+    /// A repository that issued the block template requested by `submit_block_request_for_unknown_template` and has
+    /// since removed it
+    async fn block_templates_with_retired_template() -> BlockTemplateRepository {
+        let block_templates = BlockTemplateRepository::new();
+        let template = BlockTemplateDataBuilder::default()
+            .monero_seed("abcd".to_string())
+            .tari_block(grpc::Block::default())
+            .tari_miner_data(grpc::MinerData::default())
+            .monero_difficulty(300)
+            .tari_difficulty(400)
+            .build()
+            .unwrap();
+        block_templates.save(vec![1u8; 32], template).await;
+        block_templates.remove(&[1u8; 32]).await.unwrap();
+        block_templates
+    }
+
+    #[tokio_macros::test]
+    async fn it_reports_a_stale_submission_if_enabled() {
+        let mut config = test_config();
+        config.proxy_report_stale_submissions = true;
+        let mut service = MergeMiningProxyService::new(config, block_templates_with_retired_template().await);
+        let mut resp = service.call(submit_block_request_for_unknown_template()).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert!(json["error"].is_null());
+        assert_eq!(json["id"], 1);
+        assert_eq!(json["result"]["status"], "STALE");
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_report_a_template_that_was_never_issued_as_stale() {
+        let mut config = test_config();
+        config.proxy_report_stale_submissions = true;
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+        let mut resp = service.call(submit_block_request_for_unknown_template()).await.unwrap();
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["status"], "OK");
+        assert_ne!(json["result"]["status"], "STALE");
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_report_a_stale_submission_if_disabled() {
+        let mut service = MergeMiningProxyService::new(test_config(), BlockTemplateRepository::new());
+        let mut resp = service.call(submit_block_request_for_unknown_template()).await.unwrap();
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["status"], "OK");
+    }
+
+    #[tokio_macros::test]
+    async fn it_accepts_submissions_but_refuses_other_requests_while_draining() {
+        let mut service = MergeMiningProxyService::new(test_config(), BlockTemplateRepository::new());
+        service.start_draining();
+
+        let req = Request::get("/get_height").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["error"], "The proxy is shutting down");
+
+        let mut resp = service.call(submit_block_request_for_unknown_template()).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["status"], "OK");
+    }
+}
+
+mod proxy_task_set {
+    use crate::task_set::ProxyTaskSet;
+    use futures::future;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[tokio_macros::test]
+    async fn it_stops_registered_tasks_on_shutdown() {
+        let mut tasks = ProxyTaskSet::new();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let signal = tasks.shutdown_signal();
+        let stopped_clone = stopped.clone();
+        tasks.spawn("dummy", async move {
+            let _ = signal.await;
+            stopped_clone.store(true, Ordering::SeqCst);
+        });
+
+        let not_stopped = tasks.shutdown(Duration::from_secs(5)).await;
+        assert!(not_stopped.is_empty());
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    #[tokio_macros::test]
+    async fn it_reports_tasks_that_do_not_stop_in_time() {
+        let mut tasks = ProxyTaskSet::new();
+        tasks.spawn("stubborn", future::pending());
+        let not_stopped = tasks.shutdown(Duration::from_millis(10)).await;
+        assert_eq!(not_stopped, vec!["stubborn"]);
+    }
+}
+
+mod monerod_content_type {
+    use super::*;
+    use crate::{block_template_data::BlockTemplateRepository, proxy::MergeMiningProxyService};
+    use hyper::{header, service::Service, StatusCode};
+
+    async fn bad_gateway(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from("<html><body><h1>502 Bad Gateway</h1></body></html>"))
+            .unwrap())
+    }
+
+    #[tokio_macros::test]
+    async fn it_reports_an_unexpected_content_type() {
+        let addr = start_simulated_monerod(bad_gateway);
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert_eq!(resp.status().is_success(), false);
+        let json = read_body_as_json(resp.body_mut()).await;
+        let details = json["error"]["data"]["details"].as_str().unwrap();
+        assert!(details.contains("text/html"));
+        assert!(details.contains("502 Bad Gateway"));
+    }
+}
+
+mod monerod_rpc_error {
+    use super::*;
+    use crate::{
+        block_template_data::BlockTemplateRepository,
+        error::MmProxyError,
+        proxy::{check_monerod_rpc_error, MergeMiningProxyService},
+    };
+    use hyper::{header, service::Service};
+    use serde_json::json;
+
+    async fn core_is_busy(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let body = json!({"id": "0", "jsonrpc": "2.0", "error": {"code": -9, "message": "Core is busy"}});
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap())
+    }
+
+    #[test]
+    fn it_returns_the_monerod_error() {
+        let resp = json!({"id": "0", "jsonrpc": "2.0", "error": {"code": -9, "message": "Core is busy"}});
+        let err = check_monerod_rpc_error(&resp).unwrap_err();
+        match err {
+            MmProxyError::MonerodRpcError { code, message } => {
+                assert_eq!(code, -9);
+                assert_eq!(message, "Core is busy");
+            },
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn it_accepts_a_response_without_an_error() {
+        let resp = json!({"id": "0", "jsonrpc": "2.0", "result": {"height": 1}});
+        check_monerod_rpc_error(&resp).unwrap();
+    }
+
+    #[tokio_macros::test]
+    async fn it_reports_a_get_block_template_error() {
+        let addr = start_simulated_monerod(core_is_busy);
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::post("/json_rpc")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","id":"0","method":"get_block_template"}"#,
+            ))
+            .unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert_eq!(resp.status().is_success(), false);
+        let json = read_body_as_json(resp.body_mut()).await;
+        let details = json["error"]["data"]["details"].as_str().unwrap();
+        assert!(details.contains("-9"));
+        assert!(details.contains("Core is busy"));
+    }
+}
+
+mod proxy_stats {
+    use super::*;
+    use crate::{
+        block_template_data::BlockTemplateRepository,
+        proxy::MergeMiningProxyService,
+        stats::{DifficultyHistory, ProxyStats},
+    };
+    use hyper::{header, service::Service};
+
+    async fn get_info(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"height": 100}"#))
+            .unwrap())
+    }
+
+    #[test]
+    fn it_summarises_recorded_activity() {
+        let stats = ProxyStats::new();
+        stats.set_tari_height(10);
+        stats.set_monero_height(20);
+        stats.record_submission(true);
+        stats.record_submission(true);
+        stats.record_submission(false);
+        for ms in 1..=100 {
+            stats.record_monerod_latency(Duration::from_millis(ms));
+        }
+
+        let json = stats.to_json();
+        assert_eq!(json["tari_height"], 10);
+        assert_eq!(json["monero_height"], 20);
+        assert_eq!(json["blocks_submitted"], 3);
+        assert_eq!(json["blocks_accepted"], 2);
+        assert_eq!(json["blocks_rejected"], 1);
+        assert!(json["uptime_secs"].is_u64());
+        assert_eq!(json["monerod_latency_ms"]["samples"], 100);
+        assert_eq!(json["monerod_latency_ms"]["p50"], 50);
+        assert_eq!(json["monerod_latency_ms"]["p90"], 90);
+        assert_eq!(json["monerod_latency_ms"]["p99"], 99);
+    }
+
+    #[test]
+    fn it_reports_no_percentiles_without_samples() {
+        let json = ProxyStats::new().to_json();
+        assert_eq!(json["monerod_latency_ms"]["samples"], 0);
+        assert!(json["monerod_latency_ms"]["p50"].is_null());
+    }
+
+    #[tokio_macros::test]
+    async fn it_serves_stats_as_json() {
+        let addr = start_simulated_monerod(get_info);
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+
+        let req = Request::get("/stats").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["blocks_submitted"], 0);
+        assert_eq!(json["monerod_latency_ms"]["samples"], 1);
+        assert!(json["monerod_latency_ms"]["p50"].is_u64());
+    }
+
+    #[test]
+    fn it_keeps_the_most_recent_difficulties_in_order() {
+        let history = DifficultyHistory::new(3);
+        for (height, difficulty) in (1..=5).zip(&[100, 200, 300, 400, 500]) {
+            history.record(height, *difficulty);
+        }
+
+        let json = history.to_json();
+        let entries = json.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e["difficulty"].as_u64().unwrap())
+                .collect::<Vec<_>>(),
+            vec![300, 400, 500]
+        );
+        assert_eq!(entries[0]["height"], 3);
+        assert!(entries[0]["timestamp"].is_i64());
+    }
+
+    #[test]
+    fn it_does_not_record_difficulties_if_disabled() {
+        let history = DifficultyHistory::new(0);
+        history.record(1, 100);
+        assert_eq!(history.to_json().as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn it_does_not_reserve_memory_for_a_large_capacity() {
+        let history = DifficultyHistory::new(usize::MAX);
+        history.record(1, 100);
+        assert_eq!(history.to_json().as_array().unwrap().len(), 1);
+    }
+
+    #[tokio_macros::test]
+    async fn it_serves_the_difficulty_history_as_json() {
+        let mut service = MergeMiningProxyService::new(default_test_config(), BlockTemplateRepository::new());
+        let req = Request::get("/difficulty_history").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json, serde_json::json!([]));
+    }
+}
+
+mod response_transform {
+    use super::*;
+    use crate::{
+        block_template_data::BlockTemplateRepository,
+        proxy::MergeMiningProxyService,
+        response_transform::{ResponseTransform, SetResponseField},
+    };
+    use hyper::{header, service::Service};
+    use serde_json::json;
+    use std::{str::FromStr, sync::Arc};
+
+    #[derive(Debug)]
+    struct AddField(&'static str);
+
+    impl ResponseTransform for AddField {
+        fn transform(&self, path: &str, json: &mut serde_json::Value) {
+            json[self.0] = json!(path);
+        }
+    }
+
+    #[derive(Debug)]
+    struct RemoveField(&'static str);
+
+    impl ResponseTransform for RemoveField {
+        fn transform(&self, _: &str, json: &mut serde_json::Value) {
+            if let Some(obj) = json.as_object_mut() {
+                obj.remove(self.0);
+            }
+        }
+    }
+
+    async fn get_info(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"height": 100}"#))
+            .unwrap())
+    }
+
+    #[tokio_macros::test]
+    async fn it_applies_transforms_in_order() {
+        let addr = start_simulated_monerod(get_info);
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new())
+            .with_response_transforms(vec![
+                Arc::new(AddField("first")),
+                Arc::new(AddField("second")),
+                Arc::new(RemoveField("first")),
+            ]);
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(json["height"], 100);
+        assert_eq!(json["second"], "/get_info");
+        assert!(json.get("first").is_none());
+    }
+
+    #[test]
+    fn it_sets_configured_fields() {
+        let field = SetResponseField::from_str("/result/pool_note=Merge mined with Tari").unwrap();
+        let mut json = json!({ "result": { "height": 100 } });
+        field.transform("/json_rpc", &mut json);
+        assert_eq!(json["result"]["pool_note"], "Merge mined with Tari");
+        assert_eq!(json["result"]["height"], 100);
+
+        let mut json = json!({ "error": {} });
+        field.transform("/json_rpc", &mut json);
+        assert_eq!(json, json!({ "error": {} }));
+
+        let field = SetResponseField::from_str("/a~1b=x=y").unwrap();
+        let mut json = json!({});
+        field.transform("/get_info", &mut json);
+        assert_eq!(json["a/b"], "x=y");
+        assert_eq!(field.to_string(), "/a~1b=x=y");
+    }
+
+    #[test]
+    fn it_rejects_invalid_fields() {
+        assert!(SetResponseField::from_str("/result/pool_note").is_err());
+        assert!(SetResponseField::from_str("result/pool_note=x").is_err());
+        assert!(SetResponseField::from_str("/result/=x").is_err());
+    }
+}
+
+mod block_template_id {
+    use crate::proxy::{block_template_id, is_template_id_match};
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn it_matches_an_unchanged_template() {
+        let id = block_template_id(100, 2_000_000, "abcd");
+        assert!(is_template_id_match(&HeaderValue::from_str(&id).unwrap(), &id));
+        let header = HeaderValue::from_str(&format!("\"other\", {}", id)).unwrap();
+        assert!(is_template_id_match(&header, &id));
+    }
+
+    #[test]
+    fn it_does_not_match_a_changed_template() {
+        let id = block_template_id(100, 2_000_000, "abcd");
+        let header = HeaderValue::from_str(&id).unwrap();
+        assert!(!is_template_id_match(
+            &header,
+            &block_template_id(101, 2_000_000, "abcd")
+        ));
+        assert!(!is_template_id_match(
+            &header,
+            &block_template_id(100, 2_000_001, "abcd")
+        ));
+        assert!(!is_template_id_match(
+            &header,
+            &block_template_id(100, 2_000_000, "ef01")
+        ));
+    }
+}
+
+mod template_pow_algo {
+    use super::*;
+    use crate::{
+        error::MmProxyError,
+        proxy::{check_template_pow_algo, parse_pow_algo},
+    };
+    use tari_app_grpc::tari_rpc as grpc;
+
+    fn template_with_pow_algo(pow_algo: u64) -> grpc::NewBlockTemplate {
+        grpc::NewBlockTemplate {
+            header: Some(grpc::NewBlockHeaderTemplate {
+                pow: Some(grpc::ProofOfWork {
+                    pow_algo,
+                    pow_data: Vec::new(),
+                }),
+                ..Default::default()
+            }),
+            body: None,
+        }
+    }
+
+    #[test]
+    fn it_accepts_a_template_for_the_requested_algorithm() {
+        let template = template_with_pow_algo(PowAlgorithm::Monero.as_u64());
+        check_template_pow_algo(&template, PowAlgorithm::Monero).unwrap();
+    }
+
+    #[test]
+    fn it_detects_a_template_for_a_different_algorithm() {
+        let template = template_with_pow_algo(PowAlgorithm::Sha3.as_u64());
+        match check_template_pow_algo(&template, PowAlgorithm::Monero) {
+            Err(MmProxyError::UnexpectedPowAlgorithm { expected, got }) => {
+                assert_eq!(expected, PowAlgorithm::Monero);
+                assert_eq!(got, PowAlgorithm::Sha3.as_u64());
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_only_accepts_monero_in_the_config() {
+        assert_eq!(parse_pow_algo("Monero").unwrap(), PowAlgorithm::Monero);
+        assert!(parse_pow_algo("Sha3").is_err());
+        assert!(parse_pow_algo("monero").is_err());
+    }
+
+    #[test]
+    fn it_rejects_a_template_without_pow() {
+        let template = grpc::NewBlockTemplate::default();
+        match check_template_pow_algo(&template, PowAlgorithm::Monero) {
+            Err(MmProxyError::GrpcResponseMissingField(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+mod get_block_verification {
+    use super::*;
+    use crate::{
+        block_template_data::{BlockTemplateDataBuilder, BlockTemplateRepository},
+        common::merge_mining::{
+            check_merge_mining_tag,
+            deserialize_monero_block_from_hex,
+            serialize_monero_block_to_hex,
+        },
+        proxy::MergeMiningProxyService,
+    };
+    use futures::future;
+    use hyper::{header, service::Service};
+    use monero::{
+        blockdata::{transaction::SubField, Block},
+        consensus::encode::VarInt,
+        cryptonote::hash::Hash,
+    };
+    use serde_json::json;
+    use tari_app_grpc::tari_rpc as grpc;
+    use tari_core::proof_of_work::monero_rx::append_merge_mining_tag;
+
+    const BLOCK_BLOB: &str = "0c0c8cd6a0fa057fe21d764e7abf004e975396a2160773b93712bf6118c3b4959ddd8ee0f76aad0000000002e1ea2701ffa5ea2701d5a299e2abb002028eb3066ced1b2cc82ea046f3716a48e9ae37144057d5fb48a97f941225a1957b2b0106225b7ec0a6544d8da39abe68d8bd82619b4a7c5bdae89c3783b256a8fa47820208f63aa86d2e857f070000";
+
+    fn block_with_tag(depth: u64, hash: &[u8; 32]) -> Block {
+        let mut block = deserialize_monero_block_from_hex(BLOCK_BLOB).unwrap();
+        if depth == 0 {
+            append_merge_mining_tag(&mut block, hash).unwrap();
+        } else {
+            block
+                .miner_tx
+                .prefix
+                .extra
+                .0
+                .push(SubField::MergeMining(VarInt(depth), Hash::from_slice(hash)));
+        }
+        block
+    }
+
+    #[test]
+    fn it_extracts_a_valid_tag() {
+        let block = block_with_tag(0, &[1u8; 32]);
+        assert_eq!(check_merge_mining_tag(&block).unwrap(), Hash::from_slice(&[1u8; 32]));
+    }
+
+    #[test]
+    fn it_reports_a_missing_or_malformed_tag() {
+        let block = deserialize_monero_block_from_hex(BLOCK_BLOB).unwrap();
+        assert!(check_merge_mining_tag(&block).is_err());
+        let block = block_with_tag(3, &[1u8; 32]);
+        let err = check_merge_mining_tag(&block).unwrap_err();
+        assert!(err.contains("depth 3"));
+    }
+
+    fn get_block_response(blob: String) -> Response<Body> {
+        let body = json!({"jsonrpc": "2.0", "id": 1, "result": { "blob": blob, "status": "OK" }});
+        Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn call_get_block(blob: String, block_templates: BlockTemplateRepository) -> serde_json::Value {
+        let addr = start_simulated_monerod(move |_| future::ok::<_, Infallible>(get_block_response(blob.clone())));
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        config.proxy_verify_get_block = true;
+        let mut service = MergeMiningProxyService::new(config, block_templates);
+
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": "get_block", "params": {"height": 1}});
+        let req = Request::post("/json_rpc").body(body.to_string().into()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        read_body_as_json(resp.body_mut()).await
+    }
+
+    #[tokio_macros::test]
+    async fn it_adds_the_verification_result_to_get_block_responses() {
+        let block_templates = BlockTemplateRepository::new();
+        let template = BlockTemplateDataBuilder::default()
+            .monero_seed("abcd".to_string())
+            .tari_block(grpc::Block::default())
+            .tari_miner_data(grpc::MinerData::default())
+            .monero_difficulty(1)
+            .tari_difficulty(1)
+            .build()
+            .unwrap();
+        block_templates.save(vec![1u8; 32], template).await;
+
+        let valid_blob = serialize_monero_block_to_hex(&block_with_tag(0, &[1u8; 32])).unwrap();
+        let json = call_get_block(valid_blob, block_templates.clone()).await;
+        assert_eq!(json["result"]["tari_mm_verified"], true);
+        assert_eq!(json["result"]["status"], "OK");
+
+        let unknown_blob = serialize_monero_block_to_hex(&block_with_tag(0, &[2u8; 32])).unwrap();
+        let json = call_get_block(unknown_blob, block_templates.clone()).await;
+        assert_eq!(json["result"]["tari_mm_verified"], false);
+
+        let corrupt_blob = serialize_monero_block_to_hex(&block_with_tag(3, &[1u8; 32])).unwrap();
+        let json = call_get_block(corrupt_blob, block_templates).await;
+        assert_eq!(json["result"]["tari_mm_verified"], false);
+        assert!(json["result"]["tari_mm_error"].as_str().unwrap().contains("depth"));
+        assert!(!json["result"]["blob"].as_str().unwrap().is_empty());
+    }
+}
+
+mod single_flight {
+    use crate::single_flight::SingleFlight;
+    use futures::{channel::oneshot, future, FutureExt};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::time;
+
+    #[tokio_macros::test]
+    async fn it_shares_one_build_between_concurrent_callers() {
+        let single_flight = SingleFlight::<u64, u64>::new();
+        let num_builds = Arc::new(AtomicUsize::new(0));
+
+        let results = future::join_all((0..10).map(|_| {
+            let num_builds = num_builds.clone();
+            single_flight.run(100, move || async move {
+                num_builds.fetch_add(1, Ordering::SeqCst);
+                tokio::time::delay_for(Duration::from_millis(50)).await;
+                123
+            })
+        }))
+        .await;
+
+        assert_eq!(num_builds.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|v| *v == 123));
+    }
+
+    #[tokio_macros::test]
+    async fn it_builds_again_once_the_previous_build_completes() {
+        let single_flight = SingleFlight::<u64, usize>::new();
+        let num_builds = Arc::new(AtomicUsize::new(0));
+        for expected in 1..=2 {
+            let num_builds = num_builds.clone();
+            let result = single_flight
+                .run(
+                    100,
+                    move || async move { num_builds.fetch_add(1, Ordering::SeqCst) + 1 },
+                )
+                .await;
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[tokio_macros::test]
+    async fn it_keeps_a_new_build_in_flight_when_a_late_caller_of_the_previous_one_completes() {
+        let single_flight = SingleFlight::<u64, u64>::new();
+
+        let (first_tx, first_rx) = oneshot::channel();
+        let mut late_caller = Box::pin(single_flight.run(100, || first_rx.map(|v| v.unwrap())));
+        assert!(futures::poll!(&mut late_caller).is_pending());
+        first_tx.send(1).unwrap();
+        // Give the first build time to complete
+        time::delay_for(Duration::from_millis(50)).await;
+
+        let (second_tx, second_rx) = oneshot::channel();
+        let mut second_caller = Box::pin(single_flight.run(100, || second_rx.map(|v| v.unwrap())));
+        assert!(futures::poll!(&mut second_caller).is_pending());
+        assert_eq!(late_caller.await, 1);
+
+        // The second build is still in flight, so it is shared with the next caller
+        let next_caller = single_flight.run(100, || future::ready(3));
+        second_tx.send(2).unwrap();
+        assert_eq!(future::join(second_caller, next_caller).await, (2, 2));
+    }
+
+    #[tokio_macros::test]
+    async fn it_builds_again_when_every_caller_of_the_previous_build_was_dropped() {
+        let single_flight = SingleFlight::<u64, u64>::new();
+
+        let (tx, rx) = oneshot::channel();
+        let mut caller = Box::pin(single_flight.run(100, || rx.map(|v| v.unwrap())));
+        assert!(futures::poll!(&mut caller).is_pending());
+        drop(caller);
+        tx.send(1).unwrap();
+        // Give the abandoned build time to complete
+        time::delay_for(Duration::from_millis(50)).await;
+
+        assert_eq!(single_flight.run(100, || future::ready(2)).await, 2);
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_share_builds_between_keys() {
+        let single_flight = SingleFlight::<u64, u64>::new();
+        let (a, b) = future::join(
+            single_flight.run(1, || future::ready(1)),
+            single_flight.run(2, || future::ready(2)),
+        )
+        .await;
+        assert_eq!((a, b), (1, 2));
+    }
+}
+
+mod readiness {
+    use super::*;
+    use crate::{
+        block_template_data::BlockTemplateRepository,
+        proxy::MergeMiningProxyService,
+        readiness::ReadinessGate,
+    };
+    use futures::future;
+    use hyper::{header, service::Service, StatusCode};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    async fn get_ready(service: &mut MergeMiningProxyService) -> (StatusCode, serde_json::Value) {
+        let req = Request::get("/ready").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        (resp.status(), read_body_as_json(resp.body_mut()).await)
+    }
+
+    #[tokio_macros::test]
+    async fn it_defers_requests_until_the_gate_is_ready() {
+        let num_requests = Arc::new(AtomicUsize::new(0));
+        let addr = {
+            let num_requests = num_requests.clone();
+            start_simulated_monerod(move |_| {
+                num_requests.fetch_add(1, Ordering::SeqCst);
+                future::ok::<_, Infallible>(
+                    Response::builder()
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(r#"{"height": 100}"#))
+                        .unwrap(),
+                )
+            })
+        };
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let readiness = ReadinessGate::new();
+        let mut service =
+            MergeMiningProxyService::new(config, BlockTemplateRepository::new()).with_readiness_gate(readiness.clone());
+
+        let (status, json) = get_ready(&mut service).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json["ready"], false);
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let pending = tokio::spawn(service.call(req));
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert_eq!(num_requests.load(Ordering::SeqCst), 0);
+
+        readiness.set_ready();
+        let resp = pending.await.unwrap().unwrap();
+        assert!(resp.status().is_success());
+        assert_eq!(num_requests.load(Ordering::SeqCst), 1);
+
+        let (status, json) = get_ready(&mut service).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["ready"], true);
+    }
+
+    #[tokio_macros::test]
+    async fn it_refuses_requests_that_wait_too_long_for_the_warm_up() {
+        let mut config = default_test_config();
+        config.proxy_warm_up_wait_timeout = Duration::from_millis(100);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new())
+            .with_readiness_gate(ReadinessGate::new());
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(read_body_as_json(resp.body_mut()).await["ready"], false);
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_wait_for_the_warm_up_while_draining() {
+        let mut service = MergeMiningProxyService::new(default_test_config(), BlockTemplateRepository::new())
+            .with_readiness_gate(ReadinessGate::new());
+        service.start_draining();
+
+        let req = Request::get("/get_info").body(Body::empty()).unwrap();
+        let mut resp = tokio::time::timeout(Duration::from_secs(5), service.call(req))
+            .await
+            .expect("request waited for the warm up")
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            read_body_as_json(resp.body_mut()).await["error"],
+            "The proxy is shutting down"
+        );
+    }
+
+    #[tokio_macros::test]
+    async fn it_is_ready_without_a_gate() {
+        let mut service = MergeMiningProxyService::new(default_test_config(), BlockTemplateRepository::new());
+        let (status, json) = get_ready(&mut service).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["ready"], true);
+    }
+}
+
+mod passthrough {
+    use super::*;
+    use crate::{block_template_data::BlockTemplateRepository, proxy::MergeMiningProxyService};
+    use futures::future;
+    use hyper::{header, service::Service};
+
+    const BODY_LEN: usize = 4 * 1024 * 1024;
+
+    /// Responds with a large body that is labelled as JSON but cannot be parsed as JSON
+    fn start_monerod_with_large_response() -> SocketAddr {
+        start_simulated_monerod(|_| {
+            future::ok::<_, Infallible>(
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(vec![b'x'; BODY_LEN]))
+                    .unwrap(),
+            )
+        })
+    }
+
+    #[tokio_macros::test]
+    async fn it_streams_unhandled_responses_without_parsing_them() {
+        let addr = start_monerod_with_large_response();
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        config.proxy_passthrough_unhandled_responses = true;
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/get_blocks.bin").body(Body::empty()).unwrap();
+        let mut resp = service.call(req).await.unwrap();
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        let body = proxy::read_body_until_end(resp.body_mut()).await.unwrap();
+        assert_eq!(body.len(), BODY_LEN);
+        assert!(body.iter().all(|b| *b == b'x'));
+    }
+
+    #[tokio_macros::test]
+    async fn it_parses_unhandled_responses_if_disabled() {
+        let addr = start_monerod_with_large_response();
+        let mut config = default_test_config();
+        config.monerod_url = format!("http://{}", addr);
+        let mut service = MergeMiningProxyService::new(config, BlockTemplateRepository::new());
+
+        let req = Request::get("/get_blocks.bin").body(Body::empty()).unwrap();
+        let resp = service.call(req).await.unwrap();
+        assert!(!resp.status().is_success());
+    }
+}
+
+mod block_template_persistence {
+    use crate::block_template_data::{BlockTemplateDataBuilder, BlockTemplateRepository};
+    use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+    use std::{env, fs, iter, path::PathBuf};
+    use tari_app_grpc::tari_rpc as grpc;
+
+    fn temp_file() -> PathBuf {
+        let name = iter::repeat(())
+            .map(|_| OsRng.sample(Alphanumeric))
+            .take(8)
+            .collect::<String>();
+        env::temp_dir().join(format!("mm_proxy_block_templates_{}.json", name))
+    }
+
+    fn tari_block(height: u64) -> grpc::Block {
+        grpc::Block {
+            header: Some(grpc::BlockHeader {
+                height,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    async fn save_template(block_templates: &BlockTemplateRepository, hash: u8, height: u64) {
+        let template = BlockTemplateDataBuilder::default()
+            .monero_seed("abcd".to_string())
+            .tari_block(tari_block(height))
+            .tari_miner_data(grpc::MinerData {
+                reward: 100,
+                target_difficulty: 200,
+                ..Default::default()
+            })
+            .monero_difficulty(300)
+            .tari_difficulty(400)
+            .build()
+            .unwrap();
+        block_templates.save(vec![hash; 32], template).await;
+    }
+
+    #[tokio_macros::test]
+    async fn it_reloads_block_templates_after_a_restart() {
+        let file = temp_file();
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        save_template(&block_templates, 1, 10).await;
+        save_template(&block_templates, 2, 11).await;
+        block_templates.remove(&[2u8; 32]).await;
+        drop(block_templates);
+
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        let data = block_templates.get(&[1u8; 32]).await.unwrap();
+        assert_eq!(data.monero_seed, "abcd");
+        assert_eq!(data.tari_block, tari_block(10));
+        assert_eq!(data.tari_miner_data.reward, 100);
+        assert_eq!(data.tari_miner_data.target_difficulty, 200);
+        assert_eq!(data.monero_difficulty, 300);
+        assert_eq!(data.tari_difficulty, 400);
+        assert!(block_templates.get(&[2u8; 32]).await.is_none());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[tokio_macros::test]
+    async fn it_remembers_removed_block_templates() {
+        let block_templates = BlockTemplateRepository::new();
+        save_template(&block_templates, 1, 10).await;
+        assert!(!block_templates.is_retired(&[1u8; 32]).await);
+        block_templates.remove(&[1u8; 32]).await;
+        assert!(block_templates.is_retired(&[1u8; 32]).await);
+        assert!(!block_templates.is_retired(&[2u8; 32]).await);
+    }
+
+    #[tokio_macros::test]
+    async fn it_starts_empty_if_the_file_cannot_be_loaded() {
+        let file = temp_file();
+        fs::write(&file, "not json").unwrap();
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        assert!(block_templates.get(&[1u8; 32]).await.is_none());
+
+        // The unreadable file is replaced on the next change
+        save_template(&block_templates, 1, 10).await;
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        assert!(block_templates.get(&[1u8; 32]).await.is_some());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[tokio_macros::test]
+    async fn it_skips_block_templates_that_cannot_be_loaded() {
+        let file = temp_file();
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        save_template(&block_templates, 1, 10).await;
+        drop(block_templates);
+
+        let mut persisted: Vec<serde_json::Value> = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+        let mut corrupt = persisted[0].clone();
+        corrupt["merge_mining_hash"] = serde_json::json!(hex::encode([2u8; 32]));
+        corrupt["tari_block"] = serde_json::json!("not hex");
+        persisted.push(corrupt);
+        persisted.push(serde_json::json!({ "unexpected": true }));
+        fs::write(&file, serde_json::to_vec(&persisted).unwrap()).unwrap();
+
+        let block_templates = BlockTemplateRepository::with_persistence(file.clone());
+        assert!(block_templates.get(&[1u8; 32]).await.is_some());
+        assert!(block_templates.get(&[2u8; 32]).await.is_none());
+
+        fs::remove_file(file).unwrap();
+    }
+}
+
+mod cached_block_template {
+    use crate::block_template_data::CachedBlockTemplate;
+    use serde_json::json;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn it_reuses_a_fresh_template() {
+        let template = CachedBlockTemplate::new(json!({}), "\"1-1-abcd\"".to_string());
+        assert!(!template.is_stale(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn it_refreshes_an_aged_template() {
+        let template = CachedBlockTemplate::new(json!({}), "\"1-1-abcd\"".to_string());
+        thread::sleep(Duration::from_millis(10));
+        assert!(template.is_stale(Duration::from_millis(5)));
+    }
+}
+
+mod merge_mining_proxy_config {
+    use super::*;
+
+    #[test]
+    fn it_redacts_the_monerod_password() {
+        let mut config = default_test_config();
+        config.monerod_password = "hunter2".to_string();
+        let json = config.to_redacted_json();
+        assert_eq!(json["monerod_password"], MergeMiningProxyConfig::REDACTED);
+        assert!(!json.to_string().contains("hunter2"));
+    }
+
+    #[test]
+    fn it_allows_all_clients_without_an_allowlist() {
+        let config = default_test_config();
+        assert!(config.is_client_allowed(Some("10.0.0.1:5000".parse().unwrap())));
+        assert!(config.is_client_allowed(None));
+    }
+
+    #[test]
+    fn it_only_allows_clients_in_the_allowlist() {
+        let mut config = default_test_config();
+        config.allowed_client_cidrs = vec!["127.0.0.1/32".parse().unwrap(), "2001:db8::/32".parse().unwrap()];
+        assert!(config.is_client_allowed(Some("127.0.0.1:5000".parse().unwrap())));
+        assert!(config.is_client_allowed(Some("[2001:db8::1]:5000".parse().unwrap())));
+        assert!(!config.is_client_allowed(Some("127.0.0.2:5000".parse().unwrap())));
+        assert!(!config.is_client_allowed(None));
+    }
+}
+
+mod json_rpc_response {
+    use super::*;
+    use crate::common::json_rpc::{default_block_accept_response, JsonRpcResponse};
+    use hyper::{header, StatusCode};
+    use jsonrpc::error::StandardError;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn it_builds_a_success_envelope() {
+        let v = JsonRpcResponse::success(json!(1), json!({ "height": 10 })).to_json();
+        assert_eq!(v, json!({ "id": 1, "jsonrpc": "2.0", "result": { "height": 10 } }));
+    }
+
+    #[test]
+    fn it_builds_an_error_envelope() {
+        let v = JsonRpcResponse::error(json!(1), -2, "Wrong param").to_json();
+        assert_eq!(
+            v,
+            json!({ "id": 1, "jsonrpc": "2.0", "error": { "code": -2, "message": "Wrong param" } })
+        );
+        assert!(v["result"].is_null());
+
+        let v = JsonRpcResponse::standard_error(json!(1), StandardError::InternalError, Some(json!({"details": "x"})))
+            .to_json();
+        assert_eq!(v["error"]["code"], -32603);
+        assert_eq!(v["error"]["data"]["details"], "x");
+    }
+
+    #[test]
+    fn it_echoes_the_request_id() {
+        let v = JsonRpcResponse::success(json!("0"), json!({})).to_json();
+        assert_eq!(v["id"], "0");
+        let v = JsonRpcResponse::success(Value::Null, json!({})).to_json();
+        assert!(v["id"].is_null());
+    }
+
+    #[test]
+    fn it_echoes_the_request_id_in_the_block_accept_response() {
+        let v = default_block_accept_response(json!("abc"));
+        assert_eq!(
+            v,
+            json!({ "id": "abc", "jsonrpc": "2.0", "result": "{}", "status": "OK", "untrusted": false })
+        );
+        let v = default_block_accept_response(Value::Null);
+        assert!(v["id"].is_null());
+    }
+
+    #[tokio_macros::test]
+    async fn it_creates_an_http_response() {
+        let mut resp = JsonRpcResponse::error(json!(1), -2, "Wrong param")
+            .with_status(StatusCode::BAD_REQUEST)
+            .into_response()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        let content_length = resp.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let json = read_body_as_json(resp.body_mut()).await;
+        assert_eq!(content_length, json.to_string().len());
+        assert_eq!(json["error"]["message"], "Wrong param");
+    }
 }
 
 mod add_aux_data {
     use crate::{
-        common::json_rpc,
+        common::json_rpc::JsonRpcResponse,
         proxy::{add_aux_data, MMPROXY_AUX_KEY_NAME},
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
 
     #[test]
     fn it_adds_aux_data() {
-        let v = json_rpc::success_response(None, json!({ "hello": "world"}));
+        let v = JsonRpcResponse::success(Value::Null, json!({ "hello": "world"})).to_json();
         let v = add_aux_data(v, json!({"test": "works"}));
         assert_eq!(v["result"][MMPROXY_AUX_KEY_NAME]["test"].as_str().unwrap(), "works");
     }
 
     #[test]
     fn it_merges_to_existing_aux_data() {
-        let v = json_rpc::success_response(None, json!({ "hello": "world"}));
+        let v = JsonRpcResponse::success(Value::Null, json!({ "hello": "world"})).to_json();
         let v = add_aux_data(v, json!({"test1": 1}));
         let v = add_aux_data(v, json!({"test2": 2, "test3": 3}));
         assert_eq!(v["result"][MMPROXY_AUX_KEY_NAME]["test1"].as_u64().unwrap(), 1);
@@ -98,7 +1389,7 @@ mod add_aux_data {
 
     #[test]
     fn it_does_not_add_data_to_errors() {
-        let v = json_rpc::error_response(None, 1, "it's on 🔥", None);
+        let v = JsonRpcResponse::error(Value::Null, 1, "it's on 🔥").to_json();
         let v = add_aux_data(v, json!({"it": "is broken"}));
         assert!(v["result"][MMPROXY_AUX_KEY_NAME]["it"].as_str().is_none());
     }
@@ -106,17 +1397,299 @@ mod add_aux_data {
 
 mod append_aux_chain_data {
     use crate::{
-        common::json_rpc,
+        common::json_rpc::JsonRpcResponse,
         proxy::{append_aux_chain_data, MMPROXY_AUX_KEY_NAME},
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
 
     #[test]
     fn it_adds_a_chain_object() {
-        let v = json_rpc::success_response(None, json!({}));
+        let v = JsonRpcResponse::success(Value::Null, json!({})).to_json();
         let v = append_aux_chain_data(v, json!({"test": "works"}));
         assert_eq!(v["result"][MMPROXY_AUX_KEY_NAME]["chains"].as_array().unwrap(), &[
             json!({"test": "works"})
         ]);
     }
 }
+
+mod fallback_block_template {
+    use crate::{
+        common::json_rpc::JsonRpcResponse,
+        error::MmProxyError,
+        proxy::{fallback_block_template, CoinbaseFailurePolicy, MMPROXY_AUX_KEY_NAME},
+    };
+    use serde_json::{json, Value};
+    use std::convert::TryFrom;
+
+    fn coinbase_error() -> MmProxyError {
+        MmProxyError::MissingDataError("Coinbase Invalid".to_string())
+    }
+
+    fn block_template(id: Value, height: u64, seed_hash: &str) -> Value {
+        JsonRpcResponse::success(
+            id,
+            json!({
+                "blocktemplate_blob": "abcd",
+                "height": height,
+                "prev_hash": "aa",
+                "seed_hash": seed_hash,
+            }),
+        )
+        .to_json()
+    }
+
+    #[test]
+    fn it_serves_the_last_template_as_stale() {
+        let last = block_template(1.into(), 10, "ff");
+        let fresh = block_template(2.into(), 10, "ff");
+        let resp = fallback_block_template(
+            CoinbaseFailurePolicy::ServeLastTemplate,
+            2.into(),
+            &fresh,
+            Some(last),
+            coinbase_error(),
+        )
+        .unwrap();
+        assert_eq!(resp["result"]["blocktemplate_blob"], "abcd");
+        assert_eq!(resp["result"][MMPROXY_AUX_KEY_NAME]["stale"], true);
+    }
+
+    #[test]
+    fn it_serves_the_last_template_with_the_current_request_id() {
+        let last = block_template("first".into(), 10, "ff");
+        let fresh = block_template("second".into(), 10, "ff");
+        let resp = fallback_block_template(
+            CoinbaseFailurePolicy::ServeLastTemplate,
+            "second".into(),
+            &fresh,
+            Some(last),
+            coinbase_error(),
+        )
+        .unwrap();
+        assert_eq!(resp["id"], "second");
+    }
+
+    #[test]
+    fn it_fails_if_the_monero_tip_or_seed_changed() {
+        let fresh = block_template(2.into(), 11, "ff");
+        let last = block_template(1.into(), 10, "ff");
+        let result = fallback_block_template(
+            CoinbaseFailurePolicy::ServeLastTemplate,
+            2.into(),
+            &fresh,
+            Some(last),
+            coinbase_error(),
+        );
+        assert!(matches!(result, Err(MmProxyError::MissingDataError(_))));
+
+        let fresh = block_template(2.into(), 10, "ee");
+        let last = block_template(1.into(), 10, "ff");
+        let result = fallback_block_template(
+            CoinbaseFailurePolicy::ServeLastTemplate,
+            2.into(),
+            &fresh,
+            Some(last),
+            coinbase_error(),
+        );
+        assert!(matches!(result, Err(MmProxyError::MissingDataError(_))));
+    }
+
+    #[test]
+    fn it_fails_if_there_is_no_last_template() {
+        let fresh = block_template(2.into(), 10, "ff");
+        let result = fallback_block_template(
+            CoinbaseFailurePolicy::ServeLastTemplate,
+            2.into(),
+            &fresh,
+            None,
+            coinbase_error(),
+        );
+        assert!(matches!(result, Err(MmProxyError::MissingDataError(_))));
+    }
+
+    #[test]
+    fn it_fails_if_the_policy_is_fail() {
+        let last = block_template(1.into(), 10, "ff");
+        let fresh = block_template(2.into(), 10, "ff");
+        let result = fallback_block_template(
+            CoinbaseFailurePolicy::Fail,
+            2.into(),
+            &fresh,
+            Some(last),
+            coinbase_error(),
+        );
+        assert!(matches!(result, Err(MmProxyError::MissingDataError(_))));
+    }
+
+    #[test]
+    fn it_parses_the_policy_from_config() {
+        assert_eq!(
+            CoinbaseFailurePolicy::try_from("ServeLastTemplate".to_string()).unwrap(),
+            CoinbaseFailurePolicy::ServeLastTemplate
+        );
+        assert_eq!(
+            CoinbaseFailurePolicy::try_from("Fail".to_string()).unwrap(),
+            CoinbaseFailurePolicy::Fail
+        );
+        assert!(CoinbaseFailurePolicy::try_from("bogus".to_string()).is_err());
+    }
+}
+
+mod mirror_submit_block {
+    use crate::{error::MmProxyError, proxy::select_block_submission_result};
+    use std::time::Duration;
+    use tari_app_grpc::tari_rpc as grpc;
+
+    #[test]
+    fn it_succeeds_if_one_base_node_accepts_the_block() {
+        let results = vec![
+            (
+                "127.0.0.1:18142".parse().unwrap(),
+                Err(MmProxyError::MissingDataError("rejected".to_string())),
+            ),
+            (
+                "127.0.0.1:18152".parse().unwrap(),
+                Ok(grpc::SubmitBlockResponse {
+                    block_hash: vec![1, 2, 3],
+                }),
+            ),
+        ];
+        let resp = select_block_submission_result(results).unwrap();
+        assert_eq!(resp.block_hash, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn it_returns_the_first_error_if_all_base_nodes_reject_the_block() {
+        let results = vec![
+            (
+                "127.0.0.1:18142".parse().unwrap(),
+                Err(MmProxyError::MissingDataError("primary".to_string())),
+            ),
+            (
+                "127.0.0.1:18152".parse().unwrap(),
+                Err(MmProxyError::MissingDataError("mirror".to_string())),
+            ),
+        ];
+        let err = select_block_submission_result(results).unwrap_err();
+        assert!(matches!(err, MmProxyError::MissingDataError(msg) if msg == "primary"));
+    }
+
+    #[test]
+    fn it_treats_a_timed_out_submission_as_an_error() {
+        let address = "127.0.0.1:18142".parse().unwrap();
+        let timed_out = || MmProxyError::SubmitBlockTimeout {
+            address,
+            timeout: Duration::from_secs(30),
+        };
+        let results = vec![
+            (address, Err(timed_out())),
+            (
+                "127.0.0.1:18152".parse().unwrap(),
+                Ok(grpc::SubmitBlockResponse {
+                    block_hash: vec![1, 2, 3],
+                }),
+            ),
+        ];
+        let resp = select_block_submission_result(results).unwrap();
+        assert_eq!(resp.block_hash, vec![1, 2, 3]);
+
+        let results = vec![
+            (address, Err(timed_out())),
+            (
+                "127.0.0.1:18152".parse().unwrap(),
+                Err(MmProxyError::MissingDataError("mirror".to_string())),
+            ),
+        ];
+        let err = select_block_submission_result(results).unwrap_err();
+        assert!(matches!(err, MmProxyError::SubmitBlockTimeout { address: a, .. } if a == address));
+    }
+}
+
+mod tls {
+    use crate::tls::{load_tls_acceptor, tls_incoming};
+    use hyper::{
+        server::accept,
+        service::{make_service_fn, service_fn},
+        Body,
+        Response,
+        Server,
+    };
+    use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+    use std::{convert::Infallible, env, fs, iter, net::SocketAddr, path::PathBuf, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time,
+    };
+
+    const IDENTITY_PASSWORD: &str = "tari";
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    /// Writes the self-signed test identity for `localhost` to a temporary file
+    fn identity_file() -> PathBuf {
+        let name = iter::repeat(())
+            .map(|_| OsRng.sample(Alphanumeric))
+            .take(8)
+            .collect::<String>();
+        let file = env::temp_dir().join(format!("mm_proxy_tls_identity_{}.p12", name));
+        fs::write(&file, &include_bytes!("test_data/proxy_tls_identity.p12")[..]).unwrap();
+        file
+    }
+
+    async fn start_tls_server() -> SocketAddr {
+        let file = identity_file();
+        let acceptor = load_tls_acceptor(&file, IDENTITY_PASSWORD).unwrap();
+        fs::remove_file(file).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_| async {
+                Ok::<_, Infallible>(Response::new(Body::from("ok")))
+            }))
+        });
+        let server = Server::builder(accept::from_stream(tls_incoming(listener, acceptor))).serve(make_service);
+        tokio::spawn(server);
+        addr
+    }
+
+    #[test]
+    fn it_rejects_an_identity_with_the_wrong_password() {
+        let file = identity_file();
+        let result = load_tls_acceptor(&file, "not the password");
+        fs::remove_file(file).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio_macros::test]
+    async fn it_serves_requests_over_tls() {
+        let addr = start_tls_server().await;
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let connector = tokio_tls::TlsConnector::from(connector);
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut stream = connector.connect("localhost", stream).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("ok"));
+    }
+
+    #[tokio_macros::test]
+    async fn it_does_not_serve_plaintext_requests() {
+        let addr = start_tls_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        let mut response = Vec::new();
+        // The connection is closed after the failed handshake, possibly with a TLS alert or a reset
+        let _ = time::timeout(Duration::from_secs(10), stream.read_to_end(&mut response))
+            .await
+            .unwrap();
+
+        assert!(!response.starts_with(b"HTTP/"));
+    }
+}
@@ -0,0 +1,128 @@
+//  Copyright 2021, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::error::MmProxyError;
+use futures::{future, stream, Stream, StreamExt};
+use std::{
+    fs,
+    io,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tokio_tls::{TlsAcceptor, TlsStream};
+use tracing::{debug, warn};
+
+const LOG_TARGET: &str = "tari_mm_proxy::tls";
+
+/// The time a client is given to complete the TLS handshake
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The maximum number of TLS handshakes that are processed concurrently
+const MAX_CONCURRENT_TLS_HANDSHAKES: usize = 100;
+
+/// Creates a TLS acceptor from the PKCS#12 identity (certificate chain and private key) in `identity_file`
+pub fn load_tls_acceptor(identity_file: &Path, password: &str) -> Result<TlsAcceptor, MmProxyError> {
+    let identity = native_tls::Identity::from_pkcs12(&fs::read(identity_file)?, password)?;
+    let acceptor = native_tls::TlsAcceptor::new(identity)?;
+    Ok(acceptor.into())
+}
+
+/// A TLS connection from a miner
+pub struct TlsConnection {
+    stream: TlsStream<TcpStream>,
+    remote_addr: SocketAddr,
+}
+
+impl TlsConnection {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Accepts connections on `listener` and performs the TLS handshake with each of them. Connections that fail the
+/// handshake, including plaintext HTTP requests, are logged and closed. The stream never ends.
+pub fn tls_incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> impl Stream<Item = Result<TlsConnection, io::Error>>
+{
+    stream::unfold(listener, |mut listener| async move {
+        let result = listener.accept().await;
+        Some((result, listener))
+    })
+    .filter_map(|result| async move {
+        match result {
+            Ok(conn) => Some(conn),
+            Err(err) => {
+                // Errors such as running out of file descriptors are usually temporary
+                warn!(target: LOG_TARGET, "Failed to accept a connection: {}", err);
+                time::delay_for(Duration::from_secs(1)).await;
+                None
+            },
+        }
+    })
+    .map(move |(stream, remote_addr)| {
+        let acceptor = acceptor.clone();
+        async move {
+            match time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => Some(TlsConnection { stream, remote_addr }),
+                Ok(Err(err)) => {
+                    debug!(target: LOG_TARGET, "TLS handshake with {} failed: {}", remote_addr, err);
+                    None
+                },
+                Err(_) => {
+                    debug!(target: LOG_TARGET, "TLS handshake with {} timed out", remote_addr);
+                    None
+                },
+            }
+        }
+    })
+    .buffer_unordered(MAX_CONCURRENT_TLS_HANDSHAKES)
+    .filter_map(|conn| future::ready(conn.map(Ok)))
+}
@@ -435,6 +435,7 @@ mod test {
                 flags: Default::default(),
                 message_tag: MessageTag::new(),
                 expires: None,
+                sequence: None,
             },
             authenticated_origin: None,
             source_peer,
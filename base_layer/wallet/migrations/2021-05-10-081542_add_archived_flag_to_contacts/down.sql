@@ -0,0 +1,11 @@
+PRAGMA foreign_keys=off;
+ALTER TABLE contacts RENAME TO contacts_old;
+CREATE TABLE contacts (
+    public_key BLOB PRIMARY KEY NOT NULL UNIQUE,
+    alias TEXT NOT NULL
+);
+INSERT INTO contacts (public_key, alias)
+SELECT public_key, alias
+FROM contacts_old;
+DROP TABLE contacts_old;
+PRAGMA foreign_keys=on;
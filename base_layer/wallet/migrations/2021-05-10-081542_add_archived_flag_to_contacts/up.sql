@@ -0,0 +1,2 @@
+ALTER TABLE contacts
+    ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
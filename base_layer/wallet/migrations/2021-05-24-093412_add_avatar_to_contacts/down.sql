@@ -0,0 +1,12 @@
+PRAGMA foreign_keys=off;
+ALTER TABLE contacts RENAME TO contacts_old;
+CREATE TABLE contacts (
+    public_key BLOB PRIMARY KEY NOT NULL UNIQUE,
+    alias TEXT NOT NULL,
+    archived INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO contacts (public_key, alias, archived)
+SELECT public_key, alias, archived
+FROM contacts_old;
+DROP TABLE contacts_old;
+PRAGMA foreign_keys=on;
@@ -0,0 +1,2 @@
+ALTER TABLE contacts
+    ADD COLUMN avatar BLOB NULL;
@@ -30,6 +30,8 @@ use thiserror::Error;
 pub enum ContactsServiceError {
     #[error("Contact is not found")]
     ContactNotFound,
+    #[error("Contact avatar is {size} bytes, which exceeds the maximum of {max} bytes")]
+    AvatarTooLarge { size: usize, max: usize },
     #[error("Received incorrect response from service request")]
     UnexpectedApiResponse,
     #[error("Contacts service storage error: `{0}`")]
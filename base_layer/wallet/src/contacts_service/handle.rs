@@ -28,9 +28,18 @@ use tower::Service;
 #[derive(Debug)]
 pub enum ContactsServiceRequest {
     GetContact(CommsPublicKey),
+    ContactExists(CommsPublicKey),
     UpsertContact(Contact),
     RemoveContact(CommsPublicKey),
     GetContacts,
+    GetActiveContacts,
+    GetArchivedContacts,
+    GetContactsByPublicKeys(Vec<CommsPublicKey>),
+    SearchContactsByAlias(String),
+    ArchiveContact(CommsPublicKey),
+    UnarchiveContact(CommsPublicKey),
+    GetContactAvatar(CommsPublicKey),
+    SetContactAvatar(CommsPublicKey, Option<Vec<u8>>),
 }
 
 #[derive(Debug)]
@@ -38,7 +47,12 @@ pub enum ContactsServiceResponse {
     ContactSaved,
     ContactRemoved(Contact),
     Contact(Contact),
+    ContactExists(bool),
     Contacts(Vec<Contact>),
+    ContactArchived,
+    ContactUnarchived,
+    ContactAvatar(Option<Vec<u8>>),
+    ContactAvatarSaved,
 }
 
 #[derive(Clone)]
@@ -59,6 +73,18 @@ impl ContactsServiceHandle {
         }
     }
 
+    /// Returns true if there is a contact with the given public key
+    pub async fn contact_exists(&mut self, pub_key: CommsPublicKey) -> Result<bool, ContactsServiceError> {
+        match self
+            .handle
+            .call(ContactsServiceRequest::ContactExists(pub_key))
+            .await??
+        {
+            ContactsServiceResponse::ContactExists(exists) => Ok(exists),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
     pub async fn get_contacts(&mut self) -> Result<Vec<Contact>, ContactsServiceError> {
         match self.handle.call(ContactsServiceRequest::GetContacts).await?? {
             ContactsServiceResponse::Contacts(c) => Ok(c),
@@ -66,6 +92,109 @@ impl ContactsServiceHandle {
         }
     }
 
+    /// Returns all contacts that have not been archived
+    pub async fn get_active_contacts(&mut self) -> Result<Vec<Contact>, ContactsServiceError> {
+        match self.handle.call(ContactsServiceRequest::GetActiveContacts).await?? {
+            ContactsServiceResponse::Contacts(c) => Ok(c),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Returns all archived contacts
+    pub async fn get_archived_contacts(&mut self) -> Result<Vec<Contact>, ContactsServiceError> {
+        match self.handle.call(ContactsServiceRequest::GetArchivedContacts).await?? {
+            ContactsServiceResponse::Contacts(c) => Ok(c),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Returns the contacts for the given public keys in one request. Keys without a contact are not included in the
+    /// result.
+    pub async fn get_contacts_by_public_keys(
+        &mut self,
+        pub_keys: Vec<CommsPublicKey>,
+    ) -> Result<Vec<Contact>, ContactsServiceError>
+    {
+        match self
+            .handle
+            .call(ContactsServiceRequest::GetContactsByPublicKeys(pub_keys))
+            .await??
+        {
+            ContactsServiceResponse::Contacts(c) => Ok(c),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Returns the contacts whose alias contains `query`, ordered by alias. Case is ignored for ASCII characters only.
+    pub async fn search_contacts_by_alias(&mut self, query: String) -> Result<Vec<Contact>, ContactsServiceError> {
+        match self
+            .handle
+            .call(ContactsServiceRequest::SearchContactsByAlias(query))
+            .await??
+        {
+            ContactsServiceResponse::Contacts(c) => Ok(c),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Hide a contact from the active contact list without removing it
+    pub async fn archive_contact(&mut self, pub_key: CommsPublicKey) -> Result<(), ContactsServiceError> {
+        match self
+            .handle
+            .call(ContactsServiceRequest::ArchiveContact(pub_key))
+            .await??
+        {
+            ContactsServiceResponse::ContactArchived => Ok(()),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Return an archived contact to the active contact list
+    pub async fn unarchive_contact(&mut self, pub_key: CommsPublicKey) -> Result<(), ContactsServiceError> {
+        match self
+            .handle
+            .call(ContactsServiceRequest::UnarchiveContact(pub_key))
+            .await??
+        {
+            ContactsServiceResponse::ContactUnarchived => Ok(()),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Returns the avatar of a contact, or None if the contact does not have one
+    pub async fn get_contact_avatar(
+        &mut self,
+        pub_key: CommsPublicKey,
+    ) -> Result<Option<Vec<u8>>, ContactsServiceError>
+    {
+        match self
+            .handle
+            .call(ContactsServiceRequest::GetContactAvatar(pub_key))
+            .await??
+        {
+            ContactsServiceResponse::ContactAvatar(avatar) => Ok(avatar),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Set the avatar of a contact, or clear it with None. Avatars larger than
+    /// [MAX_CONTACT_AVATAR_SIZE](crate::contacts_service::service::MAX_CONTACT_AVATAR_SIZE) are rejected.
+    pub async fn set_contact_avatar(
+        &mut self,
+        pub_key: CommsPublicKey,
+        avatar: Option<Vec<u8>>,
+    ) -> Result<(), ContactsServiceError>
+    {
+        match self
+            .handle
+            .call(ContactsServiceRequest::SetContactAvatar(pub_key, avatar))
+            .await??
+        {
+            ContactsServiceResponse::ContactAvatarSaved => Ok(()),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
     pub async fn upsert_contact(&mut self, contact: Contact) -> Result<(), ContactsServiceError> {
         match self
             .handle
@@ -31,6 +31,8 @@ use tari_service_framework::reply_channel;
 use tari_shutdown::ShutdownSignal;
 
 const LOG_TARGET: &str = "wallet:contacts_service";
+/// The maximum size in bytes of a contact avatar
+pub const MAX_CONTACT_AVATAR_SIZE: usize = 16 * 1024;
 
 pub struct ContactsService<T>
 where T: ContactsBackend + 'static
@@ -112,6 +114,11 @@ where T: ContactsBackend + 'static
             ContactsServiceRequest::GetContact(pk) => {
                 Ok(self.db.get_contact(pk).await.map(ContactsServiceResponse::Contact)?)
             },
+            ContactsServiceRequest::ContactExists(pk) => Ok(self
+                .db
+                .contact_exists(pk)
+                .await
+                .map(ContactsServiceResponse::ContactExists)?),
             ContactsServiceRequest::UpsertContact(c) => {
                 self.db.upsert_contact(c.clone()).await?;
                 info!(
@@ -131,6 +138,56 @@ where T: ContactsBackend + 'static
             ContactsServiceRequest::GetContacts => {
                 Ok(self.db.get_contacts().await.map(ContactsServiceResponse::Contacts)?)
             },
+            ContactsServiceRequest::GetActiveContacts => Ok(self
+                .db
+                .get_active_contacts()
+                .await
+                .map(ContactsServiceResponse::Contacts)?),
+            ContactsServiceRequest::GetArchivedContacts => Ok(self
+                .db
+                .get_archived_contacts()
+                .await
+                .map(ContactsServiceResponse::Contacts)?),
+            ContactsServiceRequest::GetContactsByPublicKeys(pks) => Ok(self
+                .db
+                .get_contacts_by_public_keys(pks)
+                .await
+                .map(ContactsServiceResponse::Contacts)?),
+            ContactsServiceRequest::SearchContactsByAlias(query) => Ok(self
+                .db
+                .search_contacts_by_alias(query)
+                .await
+                .map(ContactsServiceResponse::Contacts)?),
+            ContactsServiceRequest::ArchiveContact(pk) => {
+                self.db.set_contact_archived(pk.clone(), true).await?;
+                info!(target: LOG_TARGET, "Contact Archived: {}", pk);
+                Ok(ContactsServiceResponse::ContactArchived)
+            },
+            ContactsServiceRequest::UnarchiveContact(pk) => {
+                self.db.set_contact_archived(pk.clone(), false).await?;
+                info!(target: LOG_TARGET, "Contact Unarchived: {}", pk);
+                Ok(ContactsServiceResponse::ContactUnarchived)
+            },
+            ContactsServiceRequest::GetContactAvatar(pk) => Ok(self
+                .db
+                .get_contact_avatar(pk)
+                .await
+                .map(ContactsServiceResponse::ContactAvatar)?),
+            ContactsServiceRequest::SetContactAvatar(pk, avatar) => {
+                if let Some(size) = avatar
+                    .as_ref()
+                    .map(Vec::len)
+                    .filter(|size| *size > MAX_CONTACT_AVATAR_SIZE)
+                {
+                    return Err(ContactsServiceError::AvatarTooLarge {
+                        size,
+                        max: MAX_CONTACT_AVATAR_SIZE,
+                    });
+                }
+                self.db.set_contact_avatar(pk.clone(), avatar).await?;
+                info!(target: LOG_TARGET, "Contact Avatar Updated: {}", pk);
+                Ok(ContactsServiceResponse::ContactAvatarSaved)
+            },
         }
     }
 }
@@ -40,6 +40,8 @@ pub struct Contact {
 pub trait ContactsBackend: Send + Sync + Clone {
     /// Retrieve the record associated with the provided DbKey
     fn fetch(&self, key: &DbKey) -> Result<Option<DbValue>, ContactsServiceStorageError>;
+    /// Check if a record with the provided key exists in the backend. Only `DbKey::Contact` is supported.
+    fn contains(&self, key: &DbKey) -> Result<bool, ContactsServiceStorageError>;
     /// Modify the state the of the backend with a write operation
     fn write(&self, op: WriteOperation) -> Result<Option<DbValue>, ContactsServiceStorageError>;
 }
@@ -48,15 +50,23 @@ pub trait ContactsBackend: Send + Sync + Clone {
 pub enum DbKey {
     Contact(CommsPublicKey),
     Contacts,
+    ActiveContacts,
+    ArchivedContacts,
+    ContactsByPublicKeys(Vec<CommsPublicKey>),
+    ContactsByAlias(String),
+    ContactAvatar(CommsPublicKey),
 }
 
 pub enum DbValue {
     Contact(Box<Contact>),
     Contacts(Vec<Contact>),
+    ContactAvatar(Option<Vec<u8>>),
 }
 
 pub enum DbKeyValuePair {
     Contact(CommsPublicKey, Contact),
+    ContactArchived(CommsPublicKey, bool),
+    ContactAvatar(CommsPublicKey, Option<Vec<u8>>),
 }
 
 pub enum WriteOperation {
@@ -98,17 +108,55 @@ where T: ContactsBackend + 'static
             .and_then(|inner_result| inner_result)
     }
 
+    /// Returns true if there is a contact with the given public key
+    pub async fn contact_exists(&self, pub_key: CommsPublicKey) -> Result<bool, ContactsServiceStorageError> {
+        let db_clone = self.db.clone();
+        tokio::task::spawn_blocking(move || db_clone.contains(&DbKey::Contact(pub_key)))
+            .await
+            .map_err(|err| ContactsServiceStorageError::BlockingTaskSpawnError(err.to_string()))
+            .and_then(|inner_result| inner_result)
+    }
+
     pub async fn get_contacts(&self) -> Result<Vec<Contact>, ContactsServiceStorageError> {
+        self.fetch_contacts(DbKey::Contacts).await
+    }
+
+    /// Returns all contacts that have not been archived
+    pub async fn get_active_contacts(&self) -> Result<Vec<Contact>, ContactsServiceStorageError> {
+        self.fetch_contacts(DbKey::ActiveContacts).await
+    }
+
+    /// Returns all contacts that have been archived
+    pub async fn get_archived_contacts(&self) -> Result<Vec<Contact>, ContactsServiceStorageError> {
+        self.fetch_contacts(DbKey::ArchivedContacts).await
+    }
+
+    /// Returns the contacts for the given public keys in a single query. Keys that do not have a contact are skipped,
+    /// see [missing_contacts](self::missing_contacts).
+    pub async fn get_contacts_by_public_keys(
+        &self,
+        pub_keys: Vec<CommsPublicKey>,
+    ) -> Result<Vec<Contact>, ContactsServiceStorageError>
+    {
+        self.fetch_contacts(DbKey::ContactsByPublicKeys(pub_keys)).await
+    }
+
+    /// Returns the contacts whose alias contains `query`, ordered by alias. Case is ignored for ASCII characters only.
+    pub async fn search_contacts_by_alias(&self, query: String) -> Result<Vec<Contact>, ContactsServiceStorageError> {
+        self.fetch_contacts(DbKey::ContactsByAlias(query)).await
+    }
+
+    async fn fetch_contacts(&self, key: DbKey) -> Result<Vec<Contact>, ContactsServiceStorageError> {
         let db_clone = self.db.clone();
 
-        let c = tokio::task::spawn_blocking(move || match db_clone.fetch(&DbKey::Contacts) {
+        let c = tokio::task::spawn_blocking(move || match db_clone.fetch(&key) {
             Ok(None) => log_error(
-                DbKey::Contacts,
+                key,
                 ContactsServiceStorageError::UnexpectedResult("Could not retrieve contacts".to_string()),
             ),
             Ok(Some(DbValue::Contacts(c))) => Ok(c),
-            Ok(Some(other)) => unexpected_result(DbKey::Contacts, other),
-            Err(e) => log_error(DbKey::Contacts, e),
+            Ok(Some(other)) => unexpected_result(key, other),
+            Err(e) => log_error(key, e),
         })
         .await
         .map_err(|err| ContactsServiceStorageError::BlockingTaskSpawnError(err.to_string()))??;
@@ -129,6 +177,66 @@ where T: ContactsBackend + 'static
         Ok(())
     }
 
+    /// Set or clear the archived flag of the contact with the given public key. Archiving a contact hides it from the
+    /// active contact list without removing it.
+    pub async fn set_contact_archived(
+        &self,
+        pub_key: CommsPublicKey,
+        archived: bool,
+    ) -> Result<(), ContactsServiceStorageError>
+    {
+        let db_clone = self.db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            db_clone.write(WriteOperation::Upsert(DbKeyValuePair::ContactArchived(
+                pub_key, archived,
+            )))
+        })
+        .await
+        .map_err(|err| ContactsServiceStorageError::BlockingTaskSpawnError(err.to_string()))??;
+        Ok(())
+    }
+
+    /// Returns the avatar of the contact with the given public key, or None if the contact does not have one. Avatars
+    /// are stored apart from the other contact details so that listing contacts does not load them.
+    pub async fn get_contact_avatar(
+        &self,
+        pub_key: CommsPublicKey,
+    ) -> Result<Option<Vec<u8>>, ContactsServiceStorageError>
+    {
+        let db_clone = self.db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let key = DbKey::ContactAvatar(pub_key);
+            match db_clone.fetch(&key) {
+                Ok(None) => Err(ContactsServiceStorageError::ValueNotFound(key)),
+                Ok(Some(DbValue::ContactAvatar(avatar))) => Ok(avatar),
+                Ok(Some(other)) => unexpected_result(key, other),
+                Err(e) => log_error(key, e),
+            }
+        })
+        .await
+        .map_err(|err| ContactsServiceStorageError::BlockingTaskSpawnError(err.to_string()))
+        .and_then(|inner_result| inner_result)
+    }
+
+    /// Set or clear the avatar of the contact with the given public key
+    pub async fn set_contact_avatar(
+        &self,
+        pub_key: CommsPublicKey,
+        avatar: Option<Vec<u8>>,
+    ) -> Result<(), ContactsServiceStorageError>
+    {
+        let db_clone = self.db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            db_clone.write(WriteOperation::Upsert(DbKeyValuePair::ContactAvatar(pub_key, avatar)))
+        })
+        .await
+        .map_err(|err| ContactsServiceStorageError::BlockingTaskSpawnError(err.to_string()))??;
+        Ok(())
+    }
+
     pub async fn remove_contact(&self, pub_key: CommsPublicKey) -> Result<Contact, ContactsServiceStorageError> {
         let db_clone = self.db.clone();
         let pub_key_clone = pub_key.clone();
@@ -141,13 +249,22 @@ where T: ContactsBackend + 'static
 
         match result {
             DbValue::Contact(c) => Ok(*c),
-            DbValue::Contacts(_) => Err(ContactsServiceStorageError::UnexpectedResult(
+            DbValue::Contacts(_) | DbValue::ContactAvatar(_) => Err(ContactsServiceStorageError::UnexpectedResult(
                 "Incorrect response from backend.".to_string(),
             )),
         }
     }
 }
 
+/// Returns the public keys in `pub_keys` that do not have a contact in `contacts`
+pub fn missing_contacts(pub_keys: &[CommsPublicKey], contacts: &[Contact]) -> Vec<CommsPublicKey> {
+    pub_keys
+        .iter()
+        .filter(|pk| !contacts.iter().any(|c| &c.public_key == *pk))
+        .cloned()
+        .collect()
+}
+
 fn unexpected_result<T>(req: DbKey, res: DbValue) -> Result<T, ContactsServiceStorageError> {
     let msg = format!("Unexpected result for database query {}. Response: {}", req, res);
     error!(target: LOG_TARGET, "{}", msg);
@@ -159,6 +276,11 @@ impl Display for DbKey {
         match self {
             DbKey::Contact(c) => f.write_str(&format!("Contact: {:?}", c)),
             DbKey::Contacts => f.write_str(&"Contacts".to_string()),
+            DbKey::ActiveContacts => f.write_str(&"Active Contacts".to_string()),
+            DbKey::ArchivedContacts => f.write_str(&"Archived Contacts".to_string()),
+            DbKey::ContactsByPublicKeys(pks) => f.write_str(&format!("Contacts by {} public key(s)", pks.len())),
+            DbKey::ContactsByAlias(query) => f.write_str(&format!("Contacts by alias: {}", query)),
+            DbKey::ContactAvatar(c) => f.write_str(&format!("Contact avatar: {:?}", c)),
         }
     }
 }
@@ -168,6 +290,7 @@ impl Display for DbValue {
         match self {
             DbValue::Contact(_) => f.write_str(&"Contact".to_string()),
             DbValue::Contacts(_) => f.write_str(&"Contacts".to_string()),
+            DbValue::ContactAvatar(_) => f.write_str(&"Contact avatar".to_string()),
         }
     }
 }
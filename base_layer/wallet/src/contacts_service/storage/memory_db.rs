@@ -25,15 +25,30 @@ use crate::contacts_service::{
     storage::database::{Contact, ContactsBackend, DbKey, DbKeyValuePair, DbValue, WriteOperation},
 };
 use std::sync::{Arc, RwLock};
+use tari_comms::types::CommsPublicKey;
 
 #[derive(Default)]
 pub struct InnerDatabase {
     contacts: Vec<Contact>,
+    archived: Vec<CommsPublicKey>,
+    avatars: Vec<(CommsPublicKey, Vec<u8>)>,
 }
 
 impl InnerDatabase {
     pub fn new() -> Self {
-        Self { contacts: Vec::new() }
+        Self {
+            contacts: Vec::new(),
+            archived: Vec::new(),
+            avatars: Vec::new(),
+        }
+    }
+
+    fn contacts_by_archived(&self, archived: bool) -> Vec<Contact> {
+        self.contacts
+            .iter()
+            .filter(|c| self.archived.contains(&c.public_key) == archived)
+            .cloned()
+            .collect()
     }
 }
 
@@ -60,11 +75,59 @@ impl ContactsBackend for ContactsServiceMemoryDatabase {
                 .find(|v| &v.public_key == pk)
                 .map(|c| DbValue::Contact(Box::new(c.clone()))),
             DbKey::Contacts => Some(DbValue::Contacts(db.contacts.clone())),
+            DbKey::ActiveContacts => Some(DbValue::Contacts(db.contacts_by_archived(false))),
+            DbKey::ArchivedContacts => Some(DbValue::Contacts(db.contacts_by_archived(true))),
+            DbKey::ContactsByPublicKeys(pks) => Some(DbValue::Contacts(
+                db.contacts
+                    .iter()
+                    .filter(|c| pks.contains(&c.public_key))
+                    .cloned()
+                    .collect(),
+            )),
+            DbKey::ContactsByAlias(query) => {
+                // Case is only ignored for ASCII characters, the same as in the SQLite backend
+                let query = query.to_ascii_lowercase();
+                let mut contacts = db
+                    .contacts
+                    .iter()
+                    .filter(|c| c.alias.to_ascii_lowercase().contains(&query))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                contacts.sort_by(|a, b| {
+                    a.alias
+                        .to_ascii_lowercase()
+                        .cmp(&b.alias.to_ascii_lowercase())
+                        .then_with(|| a.alias.cmp(&b.alias))
+                });
+                Some(DbValue::Contacts(contacts))
+            },
+            DbKey::ContactAvatar(pk) => {
+                if db.contacts.iter().any(|c| &c.public_key == pk) {
+                    Some(DbValue::ContactAvatar(
+                        db.avatars.iter().find(|(k, _)| k == pk).map(|(_, a)| a.clone()),
+                    ))
+                } else {
+                    None
+                }
+            },
         };
 
         Ok(result)
     }
 
+    fn contains(&self, key: &DbKey) -> Result<bool, ContactsServiceStorageError> {
+        let db = acquire_read_lock!(self.db);
+        match key {
+            DbKey::Contact(pk) => Ok(db.contacts.iter().any(|c| &c.public_key == pk)),
+            DbKey::Contacts |
+            DbKey::ActiveContacts |
+            DbKey::ArchivedContacts |
+            DbKey::ContactsByPublicKeys(_) |
+            DbKey::ContactsByAlias(_) |
+            DbKey::ContactAvatar(_) => Err(ContactsServiceStorageError::OperationNotSupported),
+        }
+    }
+
     fn write(&self, op: WriteOperation) -> Result<Option<DbValue>, ContactsServiceStorageError> {
         let mut db = acquire_write_lock!(self.db);
         match op {
@@ -73,13 +136,40 @@ impl ContactsBackend for ContactsServiceMemoryDatabase {
                     None => db.contacts.push(c),
                     Some(existing_contact) => existing_contact.alias = c.alias,
                 },
+                DbKeyValuePair::ContactArchived(pk, archived) => {
+                    if !db.contacts.iter().any(|c| c.public_key == pk) {
+                        return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(pk)));
+                    }
+                    db.archived.retain(|k| k != &pk);
+                    if archived {
+                        db.archived.push(pk);
+                    }
+                },
+                DbKeyValuePair::ContactAvatar(pk, avatar) => {
+                    if !db.contacts.iter().any(|c| c.public_key == pk) {
+                        return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(pk)));
+                    }
+                    db.avatars.retain(|(k, _)| k != &pk);
+                    if let Some(avatar) = avatar {
+                        db.avatars.push((pk, avatar));
+                    }
+                },
             },
             WriteOperation::Remove(k) => match k {
                 DbKey::Contact(pk) => match db.contacts.iter().position(|c| c.public_key == pk) {
                     None => return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(pk))),
-                    Some(pos) => return Ok(Some(DbValue::Contact(Box::new(db.contacts.remove(pos))))),
+                    Some(pos) => {
+                        db.archived.retain(|k| k != &pk);
+                        db.avatars.retain(|(k, _)| k != &pk);
+                        return Ok(Some(DbValue::Contact(Box::new(db.contacts.remove(pos)))));
+                    },
                 },
-                DbKey::Contacts => {
+                DbKey::Contacts |
+                DbKey::ActiveContacts |
+                DbKey::ArchivedContacts |
+                DbKey::ContactsByPublicKeys(_) |
+                DbKey::ContactsByAlias(_) |
+                DbKey::ContactAvatar(_) => {
                     return Err(ContactsServiceStorageError::OperationNotSupported);
                 },
             },
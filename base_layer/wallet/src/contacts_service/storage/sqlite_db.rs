@@ -33,6 +33,13 @@ use std::convert::TryFrom;
 use tari_core::transactions::types::PublicKey;
 use tari_crypto::tari_utilities::ByteArray;
 
+/// The maximum number of public keys that are looked up in a single query. SQLite limits the number of variables in a
+/// statement to 999 by default.
+const MAX_PUBLIC_KEYS_PER_QUERY: usize = 500;
+
+// SQLite's built-in `lower` only folds ASCII characters, the same as `LIKE`
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
 /// A Sqlite backend for the Output Manager Service. The Backend is accessed via a connection pool to the Sqlite file.
 #[derive(Clone)]
 pub struct ContactsServiceSqliteDatabase {
@@ -60,23 +67,104 @@ impl ContactsBackend for ContactsServiceSqliteDatabase {
                     .map(|c| Contact::try_from(c.clone()))
                     .collect::<Result<Vec<_>, _>>()?,
             )),
+            DbKey::ActiveContacts => Some(DbValue::Contacts(
+                ContactSql::index_by_archived(false, &conn)?
+                    .iter()
+                    .map(|c| Contact::try_from(c.clone()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            DbKey::ArchivedContacts => Some(DbValue::Contacts(
+                ContactSql::index_by_archived(true, &conn)?
+                    .iter()
+                    .map(|c| Contact::try_from(c.clone()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            DbKey::ContactsByPublicKeys(pks) => Some(DbValue::Contacts(
+                ContactSql::find_many(&pks.iter().map(|pk| pk.to_vec()).collect::<Vec<_>>(), &conn)?
+                    .iter()
+                    .map(|c| Contact::try_from(c.clone()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            DbKey::ContactsByAlias(query) => Some(DbValue::Contacts(
+                ContactSql::search_by_alias(query, &conn)?
+                    .iter()
+                    .map(|c| Contact::try_from(c.clone()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            DbKey::ContactAvatar(pk) => match ContactSql::find_avatar(&pk.to_vec(), &(*conn)) {
+                Ok(avatar) => Some(DbValue::ContactAvatar(avatar)),
+                Err(ContactsServiceStorageError::DieselError(DieselError::NotFound)) => None,
+                Err(e) => return Err(e),
+            },
         };
 
         Ok(result)
     }
 
+    fn contains(&self, key: &DbKey) -> Result<bool, ContactsServiceStorageError> {
+        let conn = self.database_connection.acquire_lock();
+
+        match key {
+            DbKey::Contact(pk) => ContactSql::exists(&pk.to_vec(), &(*conn)),
+            DbKey::Contacts |
+            DbKey::ActiveContacts |
+            DbKey::ArchivedContacts |
+            DbKey::ContactsByPublicKeys(_) |
+            DbKey::ContactsByAlias(_) |
+            DbKey::ContactAvatar(_) => Err(ContactsServiceStorageError::OperationNotSupported),
+        }
+    }
+
     fn write(&self, op: WriteOperation) -> Result<Option<DbValue>, ContactsServiceStorageError> {
         let conn = self.database_connection.acquire_lock();
 
         match op {
             WriteOperation::Upsert(kvp) => match kvp {
-                DbKeyValuePair::Contact(k, c) => match ContactSql::find(&k.to_vec(), &(*conn)) {
+                DbKeyValuePair::Contact(k, c) => {
+                    if ContactSql::exists(&k.to_vec(), &(*conn))? {
+                        let _ = ContactSql::from(c.clone()).update(
+                            UpdateContact {
+                                alias: Some(c.alias),
+                                archived: None,
+                                avatar: None,
+                            },
+                            &(*conn),
+                        )?;
+                    } else {
+                        ContactSql::from(c).commit(&conn)?;
+                    }
+                },
+                DbKeyValuePair::ContactArchived(k, archived) => match ContactSql::find(&k.to_vec(), &(*conn)) {
                     Ok(found_c) => {
-                        let _ = found_c.update(UpdateContact { alias: Some(c.alias) }, &(*conn))?;
+                        let _ = found_c.update(
+                            UpdateContact {
+                                alias: None,
+                                archived: Some(archived as i32),
+                                avatar: None,
+                            },
+                            &(*conn),
+                        )?;
                     },
-                    Err(_) => {
-                        ContactSql::from(c).commit(&conn)?;
+                    Err(ContactsServiceStorageError::DieselError(DieselError::NotFound)) => {
+                        return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(k)));
+                    },
+                    Err(e) => return Err(e),
+                },
+                DbKeyValuePair::ContactAvatar(k, avatar) => match ContactSql::find(&k.to_vec(), &(*conn)) {
+                    Ok(found_c) => {
+                        let _ = found_c.update(
+                            UpdateContact {
+                                alias: None,
+                                archived: None,
+                                avatar: Some(avatar),
+                            },
+                            &(*conn),
+                        )?;
+                    },
+                    Err(ContactsServiceStorageError::DieselError(DieselError::NotFound)) => {
+                        return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(k)));
                     },
+                    Err(e) => return Err(e),
                 },
             },
             WriteOperation::Remove(k) => match k {
@@ -85,10 +173,17 @@ impl ContactsBackend for ContactsServiceSqliteDatabase {
                         c.delete(&conn)?;
                         return Ok(Some(DbValue::Contact(Box::new(Contact::try_from(c)?))));
                     },
-                    Err(ContactsServiceStorageError::DieselError(DieselError::NotFound)) => (),
+                    Err(ContactsServiceStorageError::DieselError(DieselError::NotFound)) => {
+                        return Err(ContactsServiceStorageError::ValueNotFound(DbKey::Contact(k)));
+                    },
                     Err(e) => return Err(e),
                 },
-                DbKey::Contacts => return Err(ContactsServiceStorageError::OperationNotSupported),
+                DbKey::Contacts |
+                DbKey::ActiveContacts |
+                DbKey::ArchivedContacts |
+                DbKey::ContactsByPublicKeys(_) |
+                DbKey::ContactsByAlias(_) |
+                DbKey::ContactAvatar(_) => return Err(ContactsServiceStorageError::OperationNotSupported),
             },
         }
 
@@ -96,12 +191,17 @@ impl ContactsBackend for ContactsServiceSqliteDatabase {
     }
 }
 
+/// The columns loaded into a `ContactSql`. The avatar is left out so that it is only loaded when it is requested.
+const CONTACT_COLUMNS: (contacts::public_key, contacts::alias, contacts::archived) =
+    (contacts::public_key, contacts::alias, contacts::archived);
+
 /// A Sql version of the Contact struct
 #[derive(Clone, Debug, Queryable, Insertable, PartialEq)]
 #[table_name = "contacts"]
 struct ContactSql {
     public_key: Vec<u8>,
     alias: String,
+    archived: i32,
 }
 
 impl ContactSql {
@@ -115,16 +215,85 @@ impl ContactSql {
 
     /// Return all contacts
     pub fn index(conn: &SqliteConnection) -> Result<Vec<ContactSql>, ContactsServiceStorageError> {
-        Ok(contacts::table.load::<ContactSql>(conn)?)
+        Ok(contacts::table.select(CONTACT_COLUMNS).load::<ContactSql>(conn)?)
+    }
+
+    /// Return all contacts with the given archived status
+    pub fn index_by_archived(
+        archived: bool,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<ContactSql>, ContactsServiceStorageError>
+    {
+        Ok(contacts::table
+            .select(CONTACT_COLUMNS)
+            .filter(contacts::archived.eq(archived as i32))
+            .load::<ContactSql>(conn)?)
     }
 
     /// Find a particular Contact, if it exists
     pub fn find(public_key: &[u8], conn: &SqliteConnection) -> Result<ContactSql, ContactsServiceStorageError> {
         Ok(contacts::table
+            .select(CONTACT_COLUMNS)
             .filter(contacts::public_key.eq(public_key))
             .first::<ContactSql>(conn)?)
     }
 
+    /// Returns true if a Contact with the given public key exists
+    pub fn exists(public_key: &[u8], conn: &SqliteConnection) -> Result<bool, ContactsServiceStorageError> {
+        Ok(diesel::select(diesel::dsl::exists(
+            contacts::table.filter(contacts::public_key.eq(public_key)),
+        ))
+        .get_result(conn)?)
+    }
+
+    /// Find the avatar of a particular Contact. The result is None if the contact exists but does not have an avatar.
+    pub fn find_avatar(
+        public_key: &[u8],
+        conn: &SqliteConnection,
+    ) -> Result<Option<Vec<u8>>, ContactsServiceStorageError>
+    {
+        Ok(contacts::table
+            .select(contacts::avatar)
+            .filter(contacts::public_key.eq(public_key))
+            .first::<Option<Vec<u8>>>(conn)?)
+    }
+
+    /// Find all the Contacts with one of the given public keys
+    pub fn find_many(
+        public_keys: &[Vec<u8>],
+        conn: &SqliteConnection,
+    ) -> Result<Vec<ContactSql>, ContactsServiceStorageError>
+    {
+        let mut found = Vec::new();
+        for chunk in public_keys.chunks(MAX_PUBLIC_KEYS_PER_QUERY) {
+            found.extend(
+                contacts::table
+                    .select(CONTACT_COLUMNS)
+                    .filter(contacts::public_key.eq_any(chunk))
+                    .load::<ContactSql>(conn)?,
+            );
+        }
+        Ok(found)
+    }
+
+    /// Find all the Contacts whose alias contains `query`, ordered by alias. The match and the order ignore case for
+    /// ASCII characters, and `%` and `_` in the query are matched literally.
+    pub fn search_by_alias(
+        query: &str,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<ContactSql>, ContactsServiceStorageError>
+    {
+        let pattern = format!(
+            "%{}%",
+            query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        Ok(contacts::table
+            .select(CONTACT_COLUMNS)
+            .filter(contacts::alias.like(pattern).escape('\\'))
+            .order((lower(contacts::alias).asc(), contacts::alias.asc()))
+            .load::<ContactSql>(conn)?)
+    }
+
     pub fn delete(&self, conn: &SqliteConnection) -> Result<(), ContactsServiceStorageError> {
         let num_deleted =
             diesel::delete(contacts::table.filter(contacts::public_key.eq(&self.public_key))).execute(conn)?;
@@ -174,6 +343,7 @@ impl From<Contact> for ContactSql {
         Self {
             public_key: o.public_key.to_vec(),
             alias: o.alias,
+            archived: 0,
         }
     }
 }
@@ -182,15 +352,20 @@ impl From<Contact> for ContactSql {
 #[table_name = "contacts"]
 pub struct UpdateContact {
     alias: Option<String>,
+    archived: Option<i32>,
+    avatar: Option<Option<Vec<u8>>>,
 }
 
 #[cfg(test)]
 mod test {
-    use crate::contacts_service::storage::{
-        database::Contact,
-        sqlite_db::{ContactSql, UpdateContact},
+    use crate::contacts_service::{
+        error::ContactsServiceStorageError,
+        storage::{
+            database::Contact,
+            sqlite_db::{ContactSql, UpdateContact},
+        },
     };
-    use diesel::{Connection, SqliteConnection};
+    use diesel::{result::Error as DieselError, Connection, SqliteConnection};
     use rand::rngs::OsRng;
     use std::convert::TryFrom;
     use tari_core::transactions::types::{PrivateKey, PublicKey};
@@ -251,6 +426,8 @@ mod test {
             c.update(
                 UpdateContact {
                     alias: Some("Fred".to_string()),
+                    archived: None,
+                    avatar: None,
                 },
                 &conn,
             )
@@ -260,4 +437,234 @@ mod test {
             assert_eq!(c_updated.alias, "Fred".to_string());
         });
     }
+
+    #[test]
+    fn test_search_by_alias() {
+        with_temp_dir(|dir_path| {
+            let db_name = format!("{}.sqlite3", string(8).as_str());
+            let db_path = format!("{}/{}", dir_path.to_str().unwrap(), db_name);
+
+            embed_migrations!("./migrations");
+            let conn =
+                SqliteConnection::establish(&db_path).unwrap_or_else(|_| panic!("Error connecting to {}", db_path));
+
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).expect("Migration failed");
+
+            for name in &["Bob", "alice2", "ALIEN", "Alice", "100%_Carol"] {
+                let pub_key = PublicKey::from_secret_key(&PrivateKey::random(&mut OsRng));
+                ContactSql::from(Contact {
+                    alias: name.to_string(),
+                    public_key: pub_key,
+                })
+                .commit(&conn)
+                .unwrap();
+            }
+
+            let aliases = |query: &str| {
+                ContactSql::search_by_alias(query, &conn)
+                    .unwrap()
+                    .into_iter()
+                    .map(|c| c.alias)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(aliases("ali"), vec![
+                "Alice".to_string(),
+                "alice2".to_string(),
+                "ALIEN".to_string()
+            ]);
+            assert_eq!(aliases("BOB"), vec!["Bob".to_string()]);
+            assert_eq!(aliases("%_"), vec!["100%_Carol".to_string()]);
+            assert_eq!(aliases("_"), vec!["100%_Carol".to_string()]);
+            assert!(aliases("Dave").is_empty());
+        });
+    }
+
+    #[test]
+    fn test_find_many() {
+        with_temp_dir(|dir_path| {
+            let db_name = format!("{}.sqlite3", string(8).as_str());
+            let db_path = format!("{}/{}", dir_path.to_str().unwrap(), db_name);
+
+            embed_migrations!("./migrations");
+            let conn =
+                SqliteConnection::establish(&db_path).unwrap_or_else(|_| panic!("Error connecting to {}", db_path));
+
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).expect("Migration failed");
+
+            // More keys than fit in a single query
+            let public_keys = (0..1200)
+                .map(|_| PublicKey::from_secret_key(&PrivateKey::random(&mut OsRng)).to_vec())
+                .collect::<Vec<_>>();
+            conn.transaction::<_, ContactsServiceStorageError, _>(|| {
+                for (i, public_key) in public_keys.iter().enumerate().filter(|(i, _)| i % 2 == 0) {
+                    ContactSql::from(Contact {
+                        alias: format!("Contact {}", i),
+                        public_key: PublicKey::from_bytes(public_key).unwrap(),
+                    })
+                    .commit(&conn)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+
+            let found = ContactSql::find_many(&public_keys, &conn).unwrap();
+            assert_eq!(found.len(), 600);
+            for (i, public_key) in public_keys.iter().enumerate() {
+                assert_eq!(found.iter().any(|c| &c.public_key == public_key), i % 2 == 0);
+            }
+        });
+    }
+
+    #[test]
+    fn test_exists_and_delete() {
+        with_temp_dir(|dir_path| {
+            let db_name = format!("{}.sqlite3", string(8).as_str());
+            let db_path = format!("{}/{}", dir_path.to_str().unwrap(), db_name);
+
+            embed_migrations!("./migrations");
+            let conn =
+                SqliteConnection::establish(&db_path).unwrap_or_else(|_| panic!("Error connecting to {}", db_path));
+
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).expect("Migration failed");
+
+            let pub_key = PublicKey::from_secret_key(&PrivateKey::random(&mut OsRng));
+            let contact = ContactSql::from(Contact {
+                alias: "Alice".to_string(),
+                public_key: pub_key.clone(),
+            });
+            assert!(!ContactSql::exists(&pub_key.to_vec(), &conn).unwrap());
+
+            contact.commit(&conn).unwrap();
+            assert!(ContactSql::exists(&pub_key.to_vec(), &conn).unwrap());
+
+            contact.delete(&conn).unwrap();
+            assert!(!ContactSql::exists(&pub_key.to_vec(), &conn).unwrap());
+            assert_eq!(
+                ContactSql::find(&pub_key.to_vec(), &conn),
+                Err(ContactsServiceStorageError::DieselError(DieselError::NotFound))
+            );
+            // Deleting a contact that does not exist is an error
+            assert_eq!(contact.delete(&conn), Err(ContactsServiceStorageError::ValuesNotFound));
+        });
+    }
+
+    #[test]
+    fn test_archive() {
+        with_temp_dir(|dir_path| {
+            let db_name = format!("{}.sqlite3", string(8).as_str());
+            let db_path = format!("{}/{}", dir_path.to_str().unwrap(), db_name);
+
+            embed_migrations!("./migrations");
+            let conn =
+                SqliteConnection::establish(&db_path).unwrap_or_else(|_| panic!("Error connecting to {}", db_path));
+
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).expect("Migration failed");
+
+            let mut contacts = Vec::new();
+            for name in &["Alice", "Bob"] {
+                let pub_key = PublicKey::from_secret_key(&PrivateKey::random(&mut OsRng));
+                let contact = Contact {
+                    alias: name.to_string(),
+                    public_key: pub_key,
+                };
+                ContactSql::from(contact.clone()).commit(&conn).unwrap();
+                contacts.push(contact);
+            }
+
+            let c = ContactSql::find(&contacts[0].public_key.to_vec(), &conn).unwrap();
+            c.update(
+                UpdateContact {
+                    alias: None,
+                    archived: Some(1),
+                    avatar: None,
+                },
+                &conn,
+            )
+            .unwrap();
+
+            let active = ContactSql::index_by_archived(false, &conn).unwrap();
+            assert_eq!(active.len(), 1);
+            assert_eq!(Contact::try_from(active[0].clone()).unwrap(), contacts[1]);
+
+            let archived = ContactSql::index_by_archived(true, &conn).unwrap();
+            assert_eq!(archived.len(), 1);
+            assert_eq!(Contact::try_from(archived[0].clone()).unwrap(), contacts[0]);
+
+            // Archived contacts are still retrievable and keep their details
+            let c = ContactSql::find(&contacts[0].public_key.to_vec(), &conn).unwrap();
+            assert_eq!(Contact::try_from(c.clone()).unwrap(), contacts[0]);
+            assert_eq!(ContactSql::index(&conn).unwrap().len(), 2);
+
+            c.update(
+                UpdateContact {
+                    alias: None,
+                    archived: Some(0),
+                    avatar: None,
+                },
+                &conn,
+            )
+            .unwrap();
+            assert_eq!(ContactSql::index_by_archived(false, &conn).unwrap().len(), 2);
+            assert!(ContactSql::index_by_archived(true, &conn).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_avatar() {
+        with_temp_dir(|dir_path| {
+            let db_name = format!("{}.sqlite3", string(8).as_str());
+            let db_path = format!("{}/{}", dir_path.to_str().unwrap(), db_name);
+
+            embed_migrations!("./migrations");
+            let conn =
+                SqliteConnection::establish(&db_path).unwrap_or_else(|_| panic!("Error connecting to {}", db_path));
+
+            embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).expect("Migration failed");
+
+            let pub_key = PublicKey::from_secret_key(&PrivateKey::random(&mut OsRng));
+            let contact = Contact {
+                alias: "Alice".to_string(),
+                public_key: pub_key,
+            };
+            ContactSql::from(contact.clone()).commit(&conn).unwrap();
+            assert_eq!(
+                ContactSql::find_avatar(&contact.public_key.to_vec(), &conn).unwrap(),
+                None
+            );
+
+            let avatar = vec![1u8, 2, 3, 4];
+            let c = ContactSql::find(&contact.public_key.to_vec(), &conn).unwrap();
+            let c = c
+                .update(
+                    UpdateContact {
+                        alias: None,
+                        archived: None,
+                        avatar: Some(Some(avatar.clone())),
+                    },
+                    &conn,
+                )
+                .unwrap();
+            assert_eq!(
+                ContactSql::find_avatar(&contact.public_key.to_vec(), &conn).unwrap(),
+                Some(avatar)
+            );
+            // Setting the avatar does not change the other contact details
+            assert_eq!(Contact::try_from(c.clone()).unwrap(), contact);
+            assert_eq!(ContactSql::index(&conn).unwrap(), vec![c.clone()]);
+
+            c.update(
+                UpdateContact {
+                    alias: None,
+                    archived: None,
+                    avatar: Some(None),
+                },
+                &conn,
+            )
+            .unwrap();
+            assert_eq!(
+                ContactSql::find_avatar(&contact.public_key.to_vec(), &conn).unwrap(),
+                None
+            );
+        });
+    }
 }
@@ -131,4 +131,22 @@ pub enum WalletStorageError {
     NoPasswordError,
     #[error("Incorrect password provided for encrypted wallet")]
     IncorrectPassword,
+    #[error("The database cannot be vacuumed while a transaction is open")]
+    VacuumInTransaction,
+    #[error(
+        "The database schema (version `{db_version}`) is newer than the newest schema supported by this wallet \
+         (version `{supported_version}`)"
+    )]
+    DatabaseSchemaTooNew {
+        db_version: String,
+        supported_version: String,
+    },
+    #[error(
+        "The database schema (version `{db_version}`) is older than the schema required by this wallet (version \
+         `{supported_version}`) and automatic migration is disabled"
+    )]
+    DatabaseSchemaOutdated {
+        db_version: String,
+        supported_version: String,
+    },
 }
@@ -31,6 +31,8 @@ table! {
     contacts (public_key) {
         public_key -> Binary,
         alias -> Text,
+        archived -> Integer,
+        avatar -> Nullable<Binary>,
     }
 }
 
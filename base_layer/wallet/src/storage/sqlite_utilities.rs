@@ -31,7 +31,13 @@ use aes_gcm::{
     aead::{generic_array::GenericArray, NewAead},
     Aes256Gcm,
 };
-use diesel::{Connection, SqliteConnection};
+use diesel::{
+    connection::TransactionManager,
+    sql_types::{BigInt, Text},
+    Connection,
+    RunQueryDsl,
+    SqliteConnection,
+};
 use digest::Digest;
 use fs2::FileExt;
 use log::*;
@@ -44,6 +50,13 @@ use tari_crypto::common::Blake256;
 
 const LOG_TARGET: &str = "wallet::storage:sqlite_utilities";
 
+embed_migrations!("./migrations");
+
+lazy_static! {
+    /// The version of the newest embedded migration, once it has been found by `latest_migration_version`
+    static ref LATEST_MIGRATION_VERSION: Mutex<Option<String>> = Mutex::new(None);
+}
+
 #[derive(Clone)]
 pub struct WalletDbConnection {
     pub connection: Arc<Mutex<SqliteConnection>>,
@@ -66,6 +79,20 @@ impl WalletDbConnection {
 pub fn run_migration_and_create_sqlite_connection<P: AsRef<Path>>(
     db_path: P,
 ) -> Result<WalletDbConnection, WalletStorageError> {
+    let (connection, _) = open_sqlite_connection(db_path, true)?;
+    Ok(connection)
+}
+
+/// Opens the wallet database at `db_path`. If the database schema is older than this wallet requires, the pending
+/// migrations are run when `auto_migrate` is true and `DatabaseSchemaOutdated` is returned otherwise. A new database
+/// counts as outdated. A database with migrations that are newer than this wallet is always rejected with
+/// `DatabaseSchemaTooNew`, as there is no way to downgrade it. Returns the connection and the versions of the
+/// migrations that were applied, oldest first.
+pub fn open_sqlite_connection<P: AsRef<Path>>(
+    db_path: P,
+    auto_migrate: bool,
+) -> Result<(WalletDbConnection, Vec<String>), WalletStorageError>
+{
     let file_lock = acquire_exclusive_file_lock(&db_path.as_ref().to_path_buf())?;
 
     let path_str = db_path
@@ -75,11 +102,82 @@ pub fn run_migration_and_create_sqlite_connection<P: AsRef<Path>>(
     let connection = SqliteConnection::establish(path_str)?;
     connection.execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 60000;")?;
 
-    embed_migrations!("./migrations");
+    let latest_version = latest_migration_version()?;
+    let versions_before = applied_migration_versions(&connection)?;
+    let db_version = versions_before.last().cloned();
+    if let Some(db_version) = db_version.as_ref().filter(|v| **v > latest_version) {
+        return Err(WalletStorageError::DatabaseSchemaTooNew {
+            db_version: db_version.clone(),
+            supported_version: latest_version,
+        });
+    }
+    if !auto_migrate && db_version.as_ref() != Some(&latest_version) {
+        return Err(WalletStorageError::DatabaseSchemaOutdated {
+            db_version: db_version.unwrap_or_else(|| "none".to_string()),
+            supported_version: latest_version,
+        });
+    }
+
     embedded_migrations::run(&connection)
         .map_err(|err| WalletStorageError::DatabaseMigrationError(format!("Database migration failed {}", err)))?;
 
-    Ok(WalletDbConnection::new(connection, Some(file_lock)))
+    let applied = applied_migration_versions(&connection)?
+        .into_iter()
+        .filter(|v| !versions_before.contains(v))
+        .collect::<Vec<_>>();
+    if !applied.is_empty() {
+        info!(
+            target: LOG_TARGET,
+            "Applied {} wallet database migration(s): {}",
+            applied.len(),
+            applied.join(", ")
+        );
+    }
+
+    Ok((WalletDbConnection::new(connection, Some(file_lock)), applied))
+}
+
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[sql_type = "Text"]
+    version: String,
+}
+
+/// Returns the version of the newest embedded migration. It is found by applying the migrations to an empty in-memory
+/// database, so that it never goes out of date when a migration is added, and cached once it has been found.
+fn latest_migration_version() -> Result<String, WalletStorageError> {
+    let mut latest_version = acquire_lock!(LATEST_MIGRATION_VERSION);
+    if let Some(version) = latest_version.as_ref() {
+        return Ok(version.clone());
+    }
+    let version = find_latest_migration_version()?;
+    *latest_version = Some(version.clone());
+    Ok(version)
+}
+
+fn find_latest_migration_version() -> Result<String, WalletStorageError> {
+    let connection = SqliteConnection::establish(":memory:")?;
+    embedded_migrations::run(&connection)
+        .map_err(|err| WalletStorageError::DatabaseMigrationError(format!("Database migration failed {}", err)))?;
+    applied_migration_versions(&connection)?
+        .pop()
+        .ok_or_else(|| WalletStorageError::DatabaseMigrationError("No database migrations are embedded".to_string()))
+}
+
+/// Returns the versions of the migrations that have been run on the database, oldest first
+fn applied_migration_versions(conn: &SqliteConnection) -> Result<Vec<String>, WalletStorageError> {
+    // This is the table that diesel uses to track migrations, which does not exist until migrations are first run
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS __diesel_schema_migrations (version VARCHAR(50) PRIMARY KEY NOT NULL, run_on \
+         TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+    )?;
+    Ok(
+        diesel::sql_query("SELECT version FROM __diesel_schema_migrations ORDER BY version")
+            .load::<MigrationVersion>(conn)?
+            .into_iter()
+            .map(|m| m.version)
+            .collect(),
+    )
 }
 
 /// This function will copy a wallet database to the provided path and then clear the CommsPrivateKey from the database.
@@ -104,6 +202,40 @@ pub async fn partial_wallet_backup<P: AsRef<Path>>(current_db: P, backup_path: P
     Ok(())
 }
 
+#[derive(QueryableByName)]
+struct DatabaseSize {
+    #[sql_type = "BigInt"]
+    size: i64,
+}
+
+fn database_size(conn: &SqliteConnection) -> Result<u64, WalletStorageError> {
+    let result =
+        diesel::sql_query("SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()")
+            .get_result::<DatabaseSize>(conn)?;
+    Ok(result.size as u64)
+}
+
+/// Rebuilds the database file using `VACUUM`, reclaiming the space left behind by deleted rows, and then runs
+/// `PRAGMA optimize`. Returns the number of bytes by which the database file shrank. This must not be called while a
+/// transaction is open on the connection.
+pub fn vacuum_sqlite_database(connection: &WalletDbConnection) -> Result<u64, WalletStorageError> {
+    let conn = connection.acquire_lock();
+    if conn.transaction_manager().get_transaction_depth() > 0 {
+        return Err(WalletStorageError::VacuumInTransaction);
+    }
+
+    let size_before = database_size(&conn)?;
+    conn.execute("VACUUM; PRAGMA optimize;")?;
+    let size_after = database_size(&conn)?;
+    let freed = size_before.saturating_sub(size_after);
+    debug!(
+        target: LOG_TARGET,
+        "Vacuumed wallet database from {} to {} bytes", size_before, size_after
+    );
+
+    Ok(freed)
+}
+
 pub fn acquire_exclusive_file_lock(db_path: &PathBuf) -> Result<File, WalletStorageError> {
     let lock_file_path = match db_path.file_name() {
         None => {
@@ -175,3 +307,124 @@ pub fn initialize_sqlite_database_backends(
         contacts_backend,
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        error::WalletStorageError,
+        storage::sqlite_utilities::{
+            latest_migration_version,
+            open_sqlite_connection,
+            run_migration_and_create_sqlite_connection,
+            vacuum_sqlite_database,
+        },
+    };
+    use diesel::Connection;
+    use tari_test_utils::random::string;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_vacuum_sqlite_database() {
+        let db_tempdir = tempdir().unwrap();
+        let db_path = db_tempdir.path().join(format!("{}.sqlite3", string(8)));
+        let connection = run_migration_and_create_sqlite_connection(&db_path).unwrap();
+
+        {
+            let conn = connection.acquire_lock();
+            for i in 0..1000 {
+                conn.execute(&format!(
+                    "INSERT INTO client_key_values (key, value) VALUES ('key{}', '{}')",
+                    i,
+                    string(1000)
+                ))
+                .unwrap();
+            }
+            conn.execute("DELETE FROM client_key_values").unwrap();
+        }
+        let size_before = std::fs::metadata(&db_path).unwrap().len();
+
+        let freed = vacuum_sqlite_database(&connection).unwrap();
+        assert!(freed > 0);
+        let size_after = std::fs::metadata(&db_path).unwrap().len();
+        assert!(size_after < size_before);
+    }
+
+    #[test]
+    fn test_latest_migration_version() {
+        // Diesel uses the digits before the first `_` in the directory name of a migration as its version
+        let newest_migration = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with('.'))
+            .max()
+            .unwrap();
+        let expected = newest_migration.split('_').next().unwrap().replace('-', "");
+        assert_eq!(latest_migration_version().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_open_sqlite_connection_migrates_old_schema() {
+        let db_tempdir = tempdir().unwrap();
+        let db_path = db_tempdir.path().join(format!("{}.sqlite3", string(8)));
+
+        // A new database has not had any migrations run
+        let err = open_sqlite_connection(&db_path, false).map(|_| ()).unwrap_err();
+        assert!(matches!(err, WalletStorageError::DatabaseSchemaOutdated { .. }));
+
+        let (connection, applied) = open_sqlite_connection(&db_path, true).unwrap();
+        assert!(applied.len() > 1);
+        assert_eq!(applied.last().unwrap(), &latest_migration_version().unwrap());
+        drop(connection);
+
+        // The database is now up to date, so it opens without migrations
+        let (connection, applied) = open_sqlite_connection(&db_path, false).unwrap();
+        assert!(applied.is_empty());
+
+        // Undo the latest migration so that the database has an older schema
+        {
+            let conn = connection.acquire_lock();
+            conn.execute(include_str!(
+                "../../migrations/2021-05-24-093412_add_avatar_to_contacts/down.sql"
+            ))
+            .unwrap();
+            conn.execute(&format!(
+                "DELETE FROM __diesel_schema_migrations WHERE version = '{}'",
+                latest_migration_version().unwrap()
+            ))
+            .unwrap();
+        }
+        drop(connection);
+
+        let err = open_sqlite_connection(&db_path, false).map(|_| ()).unwrap_err();
+        assert!(matches!(err, WalletStorageError::DatabaseSchemaOutdated { .. }));
+        let (_connection, applied) = open_sqlite_connection(&db_path, true).unwrap();
+        assert_eq!(applied, vec![latest_migration_version().unwrap()]);
+    }
+
+    #[test]
+    fn test_open_sqlite_connection_rejects_newer_schema() {
+        let db_tempdir = tempdir().unwrap();
+        let db_path = db_tempdir.path().join(format!("{}.sqlite3", string(8)));
+
+        let connection = run_migration_and_create_sqlite_connection(&db_path).unwrap();
+        connection
+            .acquire_lock()
+            .execute("INSERT INTO __diesel_schema_migrations (version) VALUES ('99991231000000')")
+            .unwrap();
+        drop(connection);
+
+        for auto_migrate in &[true, false] {
+            let err = open_sqlite_connection(&db_path, *auto_migrate).map(|_| ()).unwrap_err();
+            match err {
+                WalletStorageError::DatabaseSchemaTooNew {
+                    db_version,
+                    supported_version,
+                } => {
+                    assert_eq!(db_version, "99991231000000");
+                    assert_eq!(supported_version, latest_migration_version().unwrap());
+                },
+                err => panic!("Unexpected error: {}", err),
+            }
+        }
+    }
+}
@@ -30,8 +30,9 @@ use tari_wallet::{
     contacts_service::{
         error::{ContactsServiceError, ContactsServiceStorageError},
         handle::ContactsServiceHandle,
+        service::MAX_CONTACT_AVATAR_SIZE,
         storage::{
-            database::{Contact, ContactsBackend, ContactsDatabase, DbKey},
+            database::{missing_contacts, Contact, ContactsBackend, ContactsDatabase, DbKey},
             memory_db::ContactsServiceMemoryDatabase,
             sqlite_db::ContactsServiceSqliteDatabase,
         },
@@ -83,8 +84,12 @@ pub fn test_memory_database_crud() {
         .block_on(db.get_contact(contacts[0].public_key.clone()))
         .unwrap();
     assert_eq!(contact, contacts[0]);
+    assert!(runtime
+        .block_on(db.contact_exists(contacts[0].public_key.clone()))
+        .unwrap());
 
     let (_secret_key, public_key) = PublicKey::random_keypair(&mut OsRng);
+    assert!(!runtime.block_on(db.contact_exists(public_key.clone())).unwrap());
 
     let contact = runtime.block_on(db.get_contact(public_key.clone()));
     assert_eq!(
@@ -101,6 +106,9 @@ pub fn test_memory_database_crud() {
     let _ = runtime
         .block_on(db.remove_contact(contacts[0].public_key.clone()))
         .unwrap();
+    assert!(!runtime
+        .block_on(db.contact_exists(contacts[0].public_key.clone()))
+        .unwrap());
     contacts.remove(0);
     let got_contacts = runtime.block_on(db.get_contacts()).unwrap();
 
@@ -132,8 +140,14 @@ pub fn test_contacts_service<T: ContactsBackend + 'static>(backend: T) {
         .block_on(contacts_service.get_contact(contacts[0].public_key.clone()))
         .unwrap();
     assert_eq!(contact, contacts[0]);
+    assert!(runtime
+        .block_on(contacts_service.contact_exists(contacts[0].public_key.clone()))
+        .unwrap());
 
     let (_secret_key, public_key) = PublicKey::random_keypair(&mut OsRng);
+    assert!(!runtime
+        .block_on(contacts_service.contact_exists(public_key.clone()))
+        .unwrap());
 
     let contact = runtime.block_on(contacts_service.get_contact(public_key.clone()));
     assert_eq!(
@@ -152,6 +166,9 @@ pub fn test_contacts_service<T: ContactsBackend + 'static>(backend: T) {
     let _ = runtime
         .block_on(contacts_service.remove_contact(contacts[0].public_key.clone()))
         .unwrap();
+    assert!(!runtime
+        .block_on(contacts_service.contact_exists(contacts[0].public_key.clone()))
+        .unwrap());
     contacts.remove(0);
     let got_contacts = runtime.block_on(contacts_service.get_contacts()).unwrap();
 
@@ -164,10 +181,165 @@ pub fn test_contacts_service<T: ContactsBackend + 'static>(backend: T) {
         .block_on(contacts_service.upsert_contact(updated_contact.clone()))
         .unwrap();
     let new_contact = runtime
-        .block_on(contacts_service.get_contact(updated_contact.public_key))
+        .block_on(contacts_service.get_contact(updated_contact.public_key.clone()))
         .unwrap();
 
     assert_eq!(new_contact.alias, updated_contact.alias);
+
+    let found = runtime
+        .block_on(contacts_service.search_contacts_by_alias("fred".to_string()))
+        .unwrap();
+    assert_eq!(found, vec![updated_contact]);
+
+    runtime
+        .block_on(contacts_service.archive_contact(contacts[0].public_key.clone()))
+        .unwrap();
+    let active_contacts = runtime.block_on(contacts_service.get_active_contacts()).unwrap();
+    assert_eq!(active_contacts.len(), contacts.len() - 1);
+    assert!(!active_contacts.iter().any(|c| c.public_key == contacts[0].public_key));
+    let archived_contacts = runtime.block_on(contacts_service.get_archived_contacts()).unwrap();
+    assert_eq!(archived_contacts, vec![contacts[0].clone()]);
+    // Archived contacts are hidden from the active list but are not removed
+    let contact = runtime
+        .block_on(contacts_service.get_contact(contacts[0].public_key.clone()))
+        .unwrap();
+    assert_eq!(contact, contacts[0]);
+
+    runtime
+        .block_on(contacts_service.unarchive_contact(contacts[0].public_key.clone()))
+        .unwrap();
+    let active_contacts = runtime.block_on(contacts_service.get_active_contacts()).unwrap();
+    assert_eq!(active_contacts.len(), contacts.len());
+    assert!(runtime
+        .block_on(contacts_service.get_archived_contacts())
+        .unwrap()
+        .is_empty());
+
+    let (_secret_key, unknown_key) = PublicKey::random_keypair(&mut OsRng);
+    let pub_keys = vec![
+        contacts[0].public_key.clone(),
+        unknown_key.clone(),
+        contacts[2].public_key.clone(),
+    ];
+    let found = runtime
+        .block_on(contacts_service.get_contacts_by_public_keys(pub_keys.clone()))
+        .unwrap();
+    assert_eq!(found.len(), 2);
+    assert!(found.contains(&contacts[0]));
+    assert!(found.contains(&contacts[2]));
+    assert_eq!(missing_contacts(&pub_keys, &found), vec![unknown_key]);
+    assert!(runtime
+        .block_on(contacts_service.get_contacts_by_public_keys(Vec::new()))
+        .unwrap()
+        .is_empty());
+
+    let (_secret_key, public_key) = PublicKey::random_keypair(&mut OsRng);
+    assert_eq!(
+        runtime.block_on(contacts_service.archive_contact(public_key.clone())),
+        Err(ContactsServiceError::ContactsServiceStorageError(
+            ContactsServiceStorageError::ValueNotFound(DbKey::Contact(public_key.clone()))
+        ))
+    );
+
+    assert_eq!(
+        runtime
+            .block_on(contacts_service.get_contact_avatar(contacts[1].public_key.clone()))
+            .unwrap(),
+        None
+    );
+    let avatar = vec![7u8; MAX_CONTACT_AVATAR_SIZE];
+    runtime
+        .block_on(contacts_service.set_contact_avatar(contacts[1].public_key.clone(), Some(avatar.clone())))
+        .unwrap();
+    assert_eq!(
+        runtime
+            .block_on(contacts_service.get_contact_avatar(contacts[1].public_key.clone()))
+            .unwrap(),
+        Some(avatar.clone())
+    );
+    // The avatar is not part of the contact details
+    let contact = runtime
+        .block_on(contacts_service.get_contact(contacts[1].public_key.clone()))
+        .unwrap();
+    assert_eq!(contact, contacts[1]);
+
+    assert_eq!(
+        runtime.block_on(contacts_service.set_contact_avatar(
+            contacts[1].public_key.clone(),
+            Some(vec![7u8; MAX_CONTACT_AVATAR_SIZE + 1])
+        )),
+        Err(ContactsServiceError::AvatarTooLarge {
+            size: MAX_CONTACT_AVATAR_SIZE + 1,
+            max: MAX_CONTACT_AVATAR_SIZE,
+        })
+    );
+    // A rejected avatar leaves the existing one in place
+    assert_eq!(
+        runtime
+            .block_on(contacts_service.get_contact_avatar(contacts[1].public_key.clone()))
+            .unwrap(),
+        Some(avatar)
+    );
+
+    runtime
+        .block_on(contacts_service.set_contact_avatar(contacts[1].public_key.clone(), None))
+        .unwrap();
+    assert_eq!(
+        runtime
+            .block_on(contacts_service.get_contact_avatar(contacts[1].public_key.clone()))
+            .unwrap(),
+        None
+    );
+
+    assert_eq!(
+        runtime.block_on(contacts_service.set_contact_avatar(public_key.clone(), Some(vec![1u8]))),
+        Err(ContactsServiceError::ContactsServiceStorageError(
+            ContactsServiceStorageError::ValueNotFound(DbKey::Contact(public_key))
+        ))
+    );
+}
+
+/// Both backends must match and order aliases the same way
+pub fn test_search_contacts_by_alias<T: ContactsBackend + 'static>(backend: T) {
+    let mut runtime = Runtime::new().unwrap();
+    let db = ContactsDatabase::new(backend);
+    for alias in &["Bob", "alice2", "élan", "ALIEN", "Élise", "Alice"] {
+        let (_secret_key, public_key) = PublicKey::random_keypair(&mut OsRng);
+        runtime
+            .block_on(db.upsert_contact(Contact {
+                alias: alias.to_string(),
+                public_key,
+            }))
+            .unwrap();
+    }
+
+    let mut search = |query: &str| {
+        runtime
+            .block_on(db.search_contacts_by_alias(query.to_string()))
+            .unwrap()
+            .into_iter()
+            .map(|c| c.alias)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(search("L"), vec!["Alice", "alice2", "ALIEN", "Élise", "élan"]);
+    // Only ASCII characters are case insensitive
+    assert_eq!(search("é"), vec!["élan"]);
+    assert_eq!(search("É"), vec!["Élise"]);
+}
+
+#[test]
+fn search_contacts_by_alias_memory_db() {
+    test_search_contacts_by_alias(ContactsServiceMemoryDatabase::new());
+}
+
+#[test]
+fn search_contacts_by_alias_sqlite_db() {
+    let db_name = format!("{}.sqlite3", random_string(8).as_str());
+    let temp_dir = tempdir().unwrap();
+    let db_folder = temp_dir.path().to_str().unwrap().to_string();
+    let db_path = format!("{}/{}", db_folder, db_name);
+    let connection = run_migration_and_create_sqlite_connection(&db_path).unwrap();
+    test_search_contacts_by_alias(ContactsServiceSqliteDatabase::new(connection));
 }
 
 #[test]
@@ -94,6 +94,7 @@ pub fn create_dummy_message<T>(inner: T, public_key: &CommsPublicKey) -> DomainM
             destination: Default::default(),
             message_tag: MessageTag::new(),
             expires: None,
+            sequence: None,
         },
         authenticated_origin: None,
         source_peer: peer_source,
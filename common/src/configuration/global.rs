@@ -26,7 +26,7 @@ use super::ConfigurationError;
 use config::{Config, ConfigError, Environment};
 use multiaddr::Multiaddr;
 use std::{
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     fmt::{Display, Formatter, Result as FormatResult},
     net::SocketAddr,
     num::{NonZeroU16, TryFromIntError},
@@ -108,8 +108,26 @@ pub struct GlobalConfig {
     pub monerod_username: String,
     pub monerod_password: String,
     pub monerod_use_auth: bool,
+    pub monerod_auth_scheme: String,
+    pub monerod_validate_content_type: bool,
     pub proxy_host_address: SocketAddr,
     pub proxy_submit_to_origin: bool,
+    pub proxy_enable_debug_endpoints: bool,
+    pub proxy_coinbase_failure_policy: String,
+    pub proxy_report_stale_submissions: bool,
+    pub proxy_monero_seed_max_age: Duration,
+    pub proxy_allowed_client_cidrs: Vec<String>,
+    pub proxy_pow_algo: String,
+    pub proxy_verify_get_block: bool,
+    pub proxy_drain_timeout: Duration,
+    pub proxy_warm_up_wait_timeout: Duration,
+    pub proxy_difficulty_history_size: usize,
+    pub proxy_persist_block_templates: bool,
+    pub proxy_passthrough_unhandled_responses: bool,
+    pub proxy_mirror_submit_base_node_addresses: Vec<String>,
+    pub proxy_tls_identity_file: Option<PathBuf>,
+    pub proxy_tls_identity_password: String,
+    pub proxy_response_fields: Vec<String>,
     pub force_sync_peers: Vec<String>,
     pub wait_for_initial_sync_at_startup: bool,
     pub max_randomx_vms: usize,
@@ -566,6 +584,12 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         .get_bool(&key)
         .map_err(|e| ConfigurationError::new(&key, &e.to_string()))?;
 
+    let key = config_string("merge_mining_proxy", &net_str, "monerod_auth_scheme");
+    let monerod_auth_scheme = optional(cfg.get_str(&key))?.unwrap_or_else(|| "Basic".to_string());
+
+    let key = config_string("merge_mining_proxy", &net_str, "monerod_validate_content_type");
+    let monerod_validate_content_type = optional(cfg.get_bool(&key))?.unwrap_or(true);
+
     let key = config_string("merge_mining_proxy", &net_str, "monerod_username");
     let monerod_username = cfg
         .get_str(&key)
@@ -593,6 +617,77 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
     let key = config_string("merge_mining_proxy", &net_str, "proxy_submit_to_origin");
     let proxy_submit_to_origin = cfg.get_bool(&key).unwrap_or_else(|_| true);
 
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_enable_debug_endpoints");
+    let proxy_enable_debug_endpoints = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_coinbase_failure_policy");
+    let proxy_coinbase_failure_policy = optional(cfg.get_str(&key))?.unwrap_or_else(|| "Fail".to_string());
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_report_stale_submissions");
+    let proxy_report_stale_submissions = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_monero_seed_max_age");
+    let proxy_monero_seed_max_age = u64::try_from(optional(cfg.get_int(&key))?.unwrap_or(600))
+        .map(Duration::from_secs)
+        .map_err(|_| ConfigurationError::new(&key, "must not be negative"))?;
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_allowed_client_cidrs");
+    let proxy_allowed_client_cidrs = optional(cfg.get_array(&key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect();
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_pow_algo");
+    let proxy_pow_algo = optional(cfg.get_str(&key))?.unwrap_or_else(|| "Monero".to_string());
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_verify_get_block");
+    let proxy_verify_get_block = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_drain_timeout");
+    let proxy_drain_timeout = u64::try_from(optional(cfg.get_int(&key))?.unwrap_or(30))
+        .map(Duration::from_secs)
+        .map_err(|_| ConfigurationError::new(&key, "must not be negative"))?;
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_warm_up_wait_timeout");
+    let proxy_warm_up_wait_timeout = u64::try_from(optional(cfg.get_int(&key))?.unwrap_or(30))
+        .map(Duration::from_secs)
+        .map_err(|_| ConfigurationError::new(&key, "must not be negative"))?;
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_difficulty_history_size");
+    let proxy_difficulty_history_size = usize::try_from(optional(cfg.get_int(&key))?.unwrap_or(1000))
+        .map_err(|_| ConfigurationError::new(&key, "must not be negative"))?;
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_persist_block_templates");
+    let proxy_persist_block_templates = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_passthrough_unhandled_responses");
+    let proxy_passthrough_unhandled_responses = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
+    let key = config_string(
+        "merge_mining_proxy",
+        &net_str,
+        "proxy_mirror_submit_base_node_addresses",
+    );
+    let proxy_mirror_submit_base_node_addresses = optional(cfg.get_array(&key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect();
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_tls_identity_file");
+    let proxy_tls_identity_file = optional(cfg.get_str(&key))?.map(PathBuf::from);
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_tls_identity_password");
+    let proxy_tls_identity_password = optional(cfg.get_str(&key))?.unwrap_or_default();
+
+    let key = config_string("merge_mining_proxy", &net_str, "proxy_response_fields");
+    let proxy_response_fields = optional(cfg.get_array(&key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| v.into_str())
+        .collect::<Result<_, _>>()?;
+
     let key = "mining_node.mine_on_tip_only";
     let mine_on_tip_only = cfg.get_bool(key).unwrap_or(true);
 
@@ -655,10 +750,28 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         prevent_fee_gt_amount,
         proxy_host_address,
         proxy_submit_to_origin,
+        proxy_enable_debug_endpoints,
+        proxy_coinbase_failure_policy,
+        proxy_report_stale_submissions,
+        proxy_monero_seed_max_age,
+        proxy_allowed_client_cidrs,
+        proxy_pow_algo,
+        proxy_verify_get_block,
+        proxy_drain_timeout,
+        proxy_warm_up_wait_timeout,
+        proxy_difficulty_history_size,
+        proxy_persist_block_templates,
+        proxy_passthrough_unhandled_responses,
+        proxy_mirror_submit_base_node_addresses,
+        proxy_tls_identity_file,
+        proxy_tls_identity_password,
+        proxy_response_fields,
         monerod_url,
         monerod_username,
         monerod_password,
         monerod_use_auth,
+        monerod_auth_scheme,
+        monerod_validate_content_type,
         force_sync_peers,
         wait_for_initial_sync_at_startup,
         max_randomx_vms,
@@ -20,7 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::envelope::NodeDestination;
+use crate::envelope::{DhtMessageType, NodeDestination};
 use std::{
     fmt,
     fmt::{Display, Formatter},
@@ -79,6 +79,28 @@ impl fmt::Display for BroadcastStrategy {
 }
 
 impl BroadcastStrategy {
+    /// Returns the default broadcast strategy for messages of the given `DhtMessageType`. None is returned for message
+    /// types that are replies to a particular peer, as these must be sent directly to that peer. The caller must set a
+    /// direct strategy for these; `SendMessageParams::finish` logs an error and sends them to no peers if none was set.
+    ///
+    /// | DhtMessageType       | Default strategy   |
+    /// |----------------------|--------------------|
+    /// | `None` (domain)      | `Flood`            |
+    /// | `Join`               | `Broadcast`        |
+    /// | `Discovery`          | `Broadcast`        |
+    /// | `DiscoveryResponse`  | None (must be set) |
+    /// | `SafRequestMessages` | `Broadcast`        |
+    /// | `SafStoredMessages`  | None (must be set) |
+    pub fn default_for(dht_message_type: DhtMessageType) -> Option<Self> {
+        match dht_message_type {
+            DhtMessageType::None => Some(BroadcastStrategy::Flood(Vec::new())),
+            DhtMessageType::Join | DhtMessageType::Discovery | DhtMessageType::SafRequestMessages => {
+                Some(BroadcastStrategy::Broadcast(Vec::new()))
+            },
+            DhtMessageType::DiscoveryResponse | DhtMessageType::SafStoredMessages => None,
+        }
+    }
+
     /// Returns true if this strategy will send multiple messages, otherwise false
     pub fn is_multi_message(&self) -> bool {
         use BroadcastStrategy::*;
@@ -90,6 +112,17 @@ impl BroadcastStrategy {
         matches!(self, DirectNodeId(_) | DirectPublicKey(_))
     }
 
+    /// Returns true if this strategy sends towards a destination through other peers that may store the message for
+    /// later delivery, rather than only to the selected peers themselves
+    pub fn is_store_and_forward(&self) -> bool {
+        use BroadcastStrategy::*;
+        match self {
+            Propagate(_, _) => true,
+            Closest(request) => !request.connected_only,
+            _ => false,
+        }
+    }
+
     pub fn direct_node_id(&self) -> Option<&NodeId> {
         use BroadcastStrategy::*;
         match self {
@@ -141,6 +174,52 @@ mod test {
         assert_eq!(BroadcastStrategy::Random(0, vec![]).is_direct(), false);
     }
 
+    #[test]
+    fn is_store_and_forward() {
+        assert!(BroadcastStrategy::Propagate(Default::default(), Default::default()).is_store_and_forward());
+        assert!(BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
+            node_id: NodeId::default(),
+            excluded_peers: Default::default(),
+            connected_only: false
+        }))
+        .is_store_and_forward());
+        assert_eq!(
+            BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
+                node_id: NodeId::default(),
+                excluded_peers: Default::default(),
+                connected_only: true
+            }))
+            .is_store_and_forward(),
+            false
+        );
+        assert_eq!(
+            BroadcastStrategy::DirectNodeId(Box::new(NodeId::default())).is_store_and_forward(),
+            false
+        );
+        assert_eq!(
+            BroadcastStrategy::Flood(Default::default()).is_store_and_forward(),
+            false
+        );
+    }
+
+    #[test]
+    fn default_for() {
+        assert!(matches!(
+            BroadcastStrategy::default_for(DhtMessageType::None),
+            Some(BroadcastStrategy::Flood(_))
+        ));
+        assert!(matches!(
+            BroadcastStrategy::default_for(DhtMessageType::Discovery),
+            Some(BroadcastStrategy::Broadcast(_))
+        ));
+        assert!(matches!(
+            BroadcastStrategy::default_for(DhtMessageType::Join),
+            Some(BroadcastStrategy::Broadcast(_))
+        ));
+        assert!(BroadcastStrategy::default_for(DhtMessageType::DiscoveryResponse).is_none());
+        assert!(BroadcastStrategy::default_for(DhtMessageType::SafStoredMessages).is_none());
+    }
+
     #[test]
     fn direct_public_key() {
         assert!(BroadcastStrategy::DirectPublicKey(Box::new(CommsPublicKey::default()))
@@ -20,7 +20,13 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{dht::DhtInitializationError, outbound::DhtOutboundRequest, DbConnectionUrl, Dht, DhtConfig};
+use crate::{
+    dht::DhtInitializationError,
+    outbound::{DhtOutboundRequest, InMemorySequenceCounter, OutboundInterceptor, SequenceCounter},
+    DbConnectionUrl,
+    Dht,
+    DhtConfig,
+};
 use futures::channel::mpsc;
 use std::{sync::Arc, time::Duration};
 use tari_comms::{
@@ -35,6 +41,8 @@ pub struct DhtBuilder {
     config: DhtConfig,
     outbound_tx: mpsc::Sender<DhtOutboundRequest>,
     connectivity: ConnectivityRequester,
+    sequence_counter: Arc<dyn SequenceCounter>,
+    outbound_interceptors: Vec<Arc<dyn OutboundInterceptor>>,
     shutdown_signal: ShutdownSignal,
 }
 
@@ -56,6 +64,8 @@ impl DhtBuilder {
             peer_manager,
             outbound_tx,
             connectivity,
+            sequence_counter: Arc::new(InMemorySequenceCounter::new()),
+            outbound_interceptors: Vec::new(),
             shutdown_signal,
         }
     }
@@ -130,6 +140,18 @@ impl DhtBuilder {
         self
     }
 
+    /// Set the source of sequence numbers for outbound messages that request one. Defaults to an in-memory counter.
+    pub fn with_sequence_counter(mut self, sequence_counter: Arc<dyn SequenceCounter>) -> Self {
+        self.sequence_counter = sequence_counter;
+        self
+    }
+
+    /// Register an interceptor for outbound messages. Interceptors are called in the order that they are registered.
+    pub fn with_outbound_interceptor(mut self, interceptor: Arc<dyn OutboundInterceptor>) -> Self {
+        self.outbound_interceptors.push(interceptor);
+        self
+    }
+
     /// Build and initialize a Dht object.
     ///
     /// Will panic not in a tokio runtime context
@@ -140,6 +162,8 @@ impl DhtBuilder {
             self.peer_manager,
             self.outbound_tx,
             self.connectivity,
+            self.sequence_counter,
+            self.outbound_interceptors,
             self.shutdown_signal,
         )
         .await
@@ -20,7 +20,12 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{envelope::Network, network_discovery::NetworkDiscoveryConfig, storage::DbConnectionUrl};
+use crate::{
+    broadcast_strategy::BroadcastStrategy,
+    envelope::Network,
+    network_discovery::NetworkDiscoveryConfig,
+    storage::DbConnectionUrl,
+};
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -30,6 +35,23 @@ pub struct DhtConfig {
     /// The size of the buffer (channel) which holds pending outbound message requests.
     /// Default: 20
     pub outbound_buffer_size: usize,
+    /// The maximum number of outbound messages from a single send request that may be in flight at once. Large
+    /// broadcasts are sent in a sliding window of this size rather than all at once.
+    /// Default: 50
+    pub max_concurrent_outbound_sends: usize,
+    /// If true, outbound messages that are not sent using a store-and-forward strategy are rejected with a
+    /// `NoConnectedPeers` error when no peers are connected, instead of being queued for delivery that cannot happen.
+    /// Default: false
+    pub reject_outbound_without_connected_peers: bool,
+    /// The maximum time an outbound message that requests a minimum number of connected peers waits for them to
+    /// connect. Once it expires the message is sent to the peers that are available, subject to
+    /// `reject_outbound_without_connected_peers`.
+    /// Default: 30 seconds
+    pub min_connected_peers_timeout: Duration,
+    /// The number of connected peers that outbound messages wait for, by broadcast strategy, unless the message sets
+    /// its own minimum using `SendMessageParams::with_min_connected_peers`.
+    /// Default: 0 for every strategy
+    pub min_connected_peers: MinConnectedPeersConfig,
     /// The maximum number of peer nodes that a message has to be closer to, to be considered a neighbour
     /// Default: [DEFAULT_NUM_NEIGHBOURING_NODES](self::DEFAULT_NUM_NEIGHBOURING_NODES)
     pub num_neighbouring_nodes: usize,
@@ -153,6 +175,10 @@ impl Default for DhtConfig {
             propagation_factor: 4,
             broadcast_factor: 8,
             outbound_buffer_size: 20,
+            max_concurrent_outbound_sends: 50,
+            reject_outbound_without_connected_peers: false,
+            min_connected_peers_timeout: Duration::from_secs(30),
+            min_connected_peers: Default::default(),
             saf_num_closest_nodes: 10,
             saf_max_returned_messages: 50,
             saf_msg_storage_capacity: 100_000,
@@ -180,3 +206,31 @@ impl Default for DhtConfig {
         }
     }
 }
+
+/// The number of connected peers that outbound messages wait for before they are sent, for each kind of broadcast
+/// strategy. A value of 0 sends messages without waiting.
+#[derive(Debug, Clone, Default)]
+pub struct MinConnectedPeersConfig {
+    /// Used for `DirectNodeId` and `DirectPublicKey`
+    pub direct: usize,
+    pub flood: usize,
+    pub random: usize,
+    pub closest: usize,
+    pub broadcast: usize,
+    pub propagate: usize,
+}
+
+impl MinConnectedPeersConfig {
+    /// Returns the number of connected peers that messages sent using the given strategy wait for
+    pub fn for_strategy(&self, broadcast_strategy: &BroadcastStrategy) -> usize {
+        use BroadcastStrategy::*;
+        match broadcast_strategy {
+            DirectNodeId(_) | DirectPublicKey(_) => self.direct,
+            Flood(_) => self.flood,
+            Random(_, _) => self.random,
+            Closest(_) => self.closest,
+            Broadcast(_) => self.broadcast,
+            Propagate(_, _) => self.propagate,
+        }
+    }
+}
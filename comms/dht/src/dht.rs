@@ -31,7 +31,7 @@ use crate::{
     logging_middleware::MessageLoggingLayer,
     network_discovery::DhtNetworkDiscovery,
     outbound,
-    outbound::DhtOutboundRequest,
+    outbound::{DhtOutboundRequest, OutboundInterceptor, SequenceCounter},
     proto::envelope::DhtMessageType,
     rpc,
     storage::{DbConnection, StorageError},
@@ -99,6 +99,10 @@ pub struct Dht {
     event_publisher: DhtEventSender,
     /// Used by MetricsLayer to collect metrics and to inform heuristics for peer banning
     metrics_collector: MetricsCollectorHandle,
+    /// Source of sequence numbers for outbound messages that request one
+    sequence_counter: Arc<dyn SequenceCounter>,
+    /// Interceptors called for each outbound message request
+    outbound_interceptors: Vec<Arc<dyn OutboundInterceptor>>,
 }
 
 impl Dht {
@@ -108,6 +112,8 @@ impl Dht {
         peer_manager: Arc<PeerManager>,
         outbound_tx: mpsc::Sender<DhtOutboundRequest>,
         connectivity: ConnectivityRequester,
+        sequence_counter: Arc<dyn SequenceCounter>,
+        outbound_interceptors: Vec<Arc<dyn OutboundInterceptor>>,
         shutdown_signal: ShutdownSignal,
     ) -> Result<Self, DhtInitializationError>
     {
@@ -131,6 +137,8 @@ impl Dht {
             connectivity,
             discovery_sender,
             event_publisher: event_publisher.clone(),
+            sequence_counter,
+            outbound_interceptors,
         };
 
         let conn = DbConnection::connect_and_migrate(dht.config.database_url.clone())
@@ -353,14 +361,24 @@ impl Dht {
         S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone + Send + 'static,
         S::Future: Send,
     {
+        let mut broadcast_layer = outbound::BroadcastLayer::new(
+            Arc::clone(&self.node_identity),
+            self.dht_requester(),
+            self.discovery_service_requester(),
+            self.config.network,
+            chrono::Duration::from_std(self.config.saf_msg_validity).unwrap(),
+        )
+        .with_sequence_counter(Arc::clone(&self.sequence_counter))
+        .with_interceptors(self.outbound_interceptors.clone())
+        .with_max_concurrent_sends(self.config.max_concurrent_outbound_sends)
+        .with_min_connected_peers_wait(self.connectivity.clone(), self.config.min_connected_peers_timeout)
+        .with_min_connected_peers_defaults(self.config.min_connected_peers.clone());
+        if self.config.reject_outbound_without_connected_peers {
+            broadcast_layer = broadcast_layer.with_connected_peers_check(self.connectivity.clone());
+        }
+
         ServiceBuilder::new()
-            .layer(outbound::BroadcastLayer::new(
-                Arc::clone(&self.node_identity),
-                self.dht_requester(),
-                self.discovery_service_requester(),
-                self.config.network,
-                chrono::Duration::from_std(self.config.saf_msg_validity).unwrap(),
-            ))
+            .layer(broadcast_layer)
             .layer(MessageLoggingLayer::new(format!(
                 "Outbound [{}]",
                 self.node_identity.node_id().short_str()
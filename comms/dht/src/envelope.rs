@@ -139,6 +139,8 @@ pub struct DhtMessageHeader {
     pub flags: DhtMessageFlags,
     pub message_tag: MessageTag,
     pub expires: Option<EpochTime>,
+    /// Sequence number of the message for the receiving peer, if the sender set one
+    pub sequence: Option<u64>,
 }
 
 impl DhtMessageHeader {
@@ -194,6 +196,7 @@ impl TryFrom<DhtHeader> for DhtMessageHeader {
             flags: DhtMessageFlags::from_bits(header.flags).ok_or_else(|| DhtMessageError::InvalidMessageFlags)?,
             message_tag: MessageTag::from(header.message_tag),
             expires: expires.map(datetime_to_epochtime),
+            sequence: Some(header.sequence).filter(|s| *s > 0),
         })
     }
 }
@@ -226,6 +229,7 @@ impl From<DhtMessageHeader> for DhtHeader {
             flags: header.flags.bits(),
             message_tag: header.message_tag.as_value(),
             expires: expires.map(datetime_to_timestamp),
+            sequence: header.sequence.unwrap_or(0),
         }
     }
 }
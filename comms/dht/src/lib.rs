@@ -136,7 +136,7 @@ mod connectivity;
 pub use connectivity::MetricsCollectorHandle;
 
 mod config;
-pub use config::DhtConfig;
+pub use config::{DhtConfig, MinConnectedPeersConfig};
 
 mod consts;
 mod crypt;
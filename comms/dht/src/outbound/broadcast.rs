@@ -24,6 +24,7 @@ use super::{error::DhtOutboundError, message::DhtOutboundRequest};
 use crate::{
     actor::DhtRequester,
     broadcast_strategy::BroadcastStrategy,
+    config::MinConnectedPeersConfig,
     crypt,
     discovery::DhtDiscoveryRequester,
     envelope::{datetime_to_timestamp, DhtMessageFlags, DhtMessageHeader, NodeDestination},
@@ -31,7 +32,11 @@ use crate::{
         message::{DhtOutboundMessage, OutboundEncryption, SendFailure},
         message_params::FinalSendMessageParams,
         message_send_state::MessageSendState,
+        ordering::{OrderedSend, OrderedSendQueues},
+        InMemorySequenceCounter,
+        OutboundInterceptor,
         SendMessageResponse,
+        SequenceCounter,
     },
     proto::envelope::{DhtMessageType, Network, OriginMac},
 };
@@ -47,8 +52,9 @@ use futures::{
 };
 use log::*;
 use rand::rngs::OsRng;
-use std::{sync::Arc, task::Poll};
+use std::{cmp, sync::Arc, task::Poll, time::Duration};
 use tari_comms::{
+    connectivity::ConnectivityRequester,
     message::{MessageExt, MessageTag},
     peer_manager::{NodeId, NodeIdentity, Peer},
     pipeline::PipelineError,
@@ -60,9 +66,12 @@ use tari_crypto::{
     tari_utilities::{message_format::MessageFormat, ByteArray},
 };
 use tari_utilities::hex::Hex;
-use tower::{layer::Layer, Service, ServiceExt};
+use tokio::time::{self, Instant};
+use tower::{layer::Layer, limit::ConcurrencyLimit, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::outbound::broadcast_middleware";
+const DEFAULT_MAX_CONCURRENT_SENDS: usize = 50;
+const DEFAULT_MIN_CONNECTED_PEERS_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct BroadcastLayer {
     dht_requester: DhtRequester,
@@ -70,6 +79,13 @@ pub struct BroadcastLayer {
     node_identity: Arc<NodeIdentity>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    sequence_counter: Arc<dyn SequenceCounter>,
+    interceptors: Vec<Arc<dyn OutboundInterceptor>>,
+    max_concurrent_sends: usize,
+    connectivity: Option<ConnectivityRequester>,
+    reject_without_connected_peers: bool,
+    min_connected_peers_timeout: Duration,
+    min_connected_peers: MinConnectedPeersConfig,
 }
 
 impl BroadcastLayer {
@@ -87,15 +103,64 @@ impl BroadcastLayer {
             dht_discovery_requester,
             target_network,
             message_validity_window,
+            sequence_counter: Arc::new(InMemorySequenceCounter::new()),
+            interceptors: Vec::new(),
+            max_concurrent_sends: DEFAULT_MAX_CONCURRENT_SENDS,
+            connectivity: None,
+            reject_without_connected_peers: false,
+            min_connected_peers_timeout: DEFAULT_MIN_CONNECTED_PEERS_TIMEOUT,
+            min_connected_peers: Default::default(),
         }
     }
+
+    /// Set the source of sequence numbers for messages that request one
+    pub fn with_sequence_counter(mut self, sequence_counter: Arc<dyn SequenceCounter>) -> Self {
+        self.sequence_counter = sequence_counter;
+        self
+    }
+
+    /// Set the interceptors that are called, in order, for each outbound message request
+    pub fn with_interceptors(mut self, interceptors: Vec<Arc<dyn OutboundInterceptor>>) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Set the maximum number of messages from a single request that may be sent concurrently
+    pub fn with_max_concurrent_sends(mut self, max_concurrent_sends: usize) -> Self {
+        self.max_concurrent_sends = max_concurrent_sends;
+        self
+    }
+
+    /// Reject requests that do not use a store-and-forward strategy when `connectivity` reports no connected peers
+    pub fn with_connected_peers_check(mut self, connectivity: ConnectivityRequester) -> Self {
+        self.connectivity = Some(connectivity);
+        self.reject_without_connected_peers = true;
+        self
+    }
+
+    /// Wait up to `timeout` for the number of connected peers requested in `SendMessageParams::min_connected_peers`.
+    /// When the timeout expires the request proceeds with the peers that are connected, after which the connected
+    /// peers check (if enabled) rejects the request if there are none.
+    pub fn with_min_connected_peers_wait(mut self, connectivity: ConnectivityRequester, timeout: Duration) -> Self {
+        self.connectivity = Some(connectivity);
+        self.min_connected_peers_timeout = timeout;
+        self
+    }
+
+    /// Set the number of connected peers that messages wait for, by broadcast strategy, if they do not set their own
+    /// minimum using `SendMessageParams::with_min_connected_peers`. This has no effect unless
+    /// [with_min_connected_peers_wait](Self::with_min_connected_peers_wait) is used.
+    pub fn with_min_connected_peers_defaults(mut self, min_connected_peers: MinConnectedPeersConfig) -> Self {
+        self.min_connected_peers = min_connected_peers;
+        self
+    }
 }
 
 impl<S> Layer<S> for BroadcastLayer {
     type Service = BroadcastMiddleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        BroadcastMiddleware::new(
+        let middleware = BroadcastMiddleware::new(
             service,
             Arc::clone(&self.node_identity),
             self.dht_requester.clone(),
@@ -103,6 +168,19 @@ impl<S> Layer<S> for BroadcastLayer {
             self.target_network,
             self.message_validity_window,
         )
+        .with_sequence_counter(Arc::clone(&self.sequence_counter))
+        .with_interceptors(self.interceptors.clone())
+        .with_max_concurrent_sends(self.max_concurrent_sends)
+        .with_min_connected_peers_defaults(self.min_connected_peers.clone());
+        match self.connectivity.clone() {
+            Some(connectivity) if self.reject_without_connected_peers => middleware
+                .with_min_connected_peers_wait(connectivity.clone(), self.min_connected_peers_timeout)
+                .with_connected_peers_check(connectivity),
+            Some(connectivity) => {
+                middleware.with_min_connected_peers_wait(connectivity, self.min_connected_peers_timeout)
+            },
+            None => middleware,
+        }
     }
 }
 
@@ -116,6 +194,14 @@ pub struct BroadcastMiddleware<S> {
     node_identity: Arc<NodeIdentity>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    sequence_counter: Arc<dyn SequenceCounter>,
+    interceptors: Vec<Arc<dyn OutboundInterceptor>>,
+    max_concurrent_sends: usize,
+    connectivity: Option<ConnectivityRequester>,
+    reject_without_connected_peers: bool,
+    min_connected_peers_timeout: Duration,
+    min_connected_peers: MinConnectedPeersConfig,
+    ordered_sends: OrderedSendQueues,
 }
 
 impl<S> BroadcastMiddleware<S> {
@@ -135,8 +221,58 @@ impl<S> BroadcastMiddleware<S> {
             node_identity,
             target_network,
             message_validity_window,
+            sequence_counter: Arc::new(InMemorySequenceCounter::new()),
+            interceptors: Vec::new(),
+            max_concurrent_sends: DEFAULT_MAX_CONCURRENT_SENDS,
+            connectivity: None,
+            reject_without_connected_peers: false,
+            min_connected_peers_timeout: DEFAULT_MIN_CONNECTED_PEERS_TIMEOUT,
+            min_connected_peers: Default::default(),
+            ordered_sends: OrderedSendQueues::new(),
         }
     }
+
+    /// Set the source of sequence numbers for messages that request one
+    pub fn with_sequence_counter(mut self, sequence_counter: Arc<dyn SequenceCounter>) -> Self {
+        self.sequence_counter = sequence_counter;
+        self
+    }
+
+    /// Set the interceptors that are called, in order, for each outbound message request
+    pub fn with_interceptors(mut self, interceptors: Vec<Arc<dyn OutboundInterceptor>>) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Set the maximum number of messages from a single request that may be sent concurrently
+    pub fn with_max_concurrent_sends(mut self, max_concurrent_sends: usize) -> Self {
+        self.max_concurrent_sends = max_concurrent_sends;
+        self
+    }
+
+    /// Reject requests that do not use a store-and-forward strategy when `connectivity` reports no connected peers
+    pub fn with_connected_peers_check(mut self, connectivity: ConnectivityRequester) -> Self {
+        self.connectivity = Some(connectivity);
+        self.reject_without_connected_peers = true;
+        self
+    }
+
+    /// Wait up to `timeout` for the number of connected peers requested in `SendMessageParams::min_connected_peers`.
+    /// When the timeout expires the request proceeds with the peers that are connected, after which the connected
+    /// peers check (if enabled) rejects the request if there are none.
+    pub fn with_min_connected_peers_wait(mut self, connectivity: ConnectivityRequester, timeout: Duration) -> Self {
+        self.connectivity = Some(connectivity);
+        self.min_connected_peers_timeout = timeout;
+        self
+    }
+
+    /// Set the number of connected peers that messages wait for, by broadcast strategy, if they do not set their own
+    /// minimum using `SendMessageParams::with_min_connected_peers`. This has no effect unless
+    /// [with_min_connected_peers_wait](Self::with_min_connected_peers_wait) is used.
+    pub fn with_min_connected_peers_defaults(mut self, min_connected_peers: MinConnectedPeersConfig) -> Self {
+        self.min_connected_peers = min_connected_peers;
+        self
+    }
 }
 
 impl<S> Service<DhtOutboundRequest> for BroadcastMiddleware<S>
@@ -152,6 +288,23 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError> + Clo
     }
 
     fn call(&mut self, msg: DhtOutboundRequest) -> Self::Future {
+        // The place in the queue is taken here, rather than in the task, so that ordered requests are processed in the
+        // order that they are submitted
+        let ordered_send = match &msg {
+            DhtOutboundRequest::SendMessage(params, _, _) if params.ordered_delivery => params
+                .broadcast_strategy
+                .direct_node_id()
+                .cloned()
+                .or_else(|| {
+                    params
+                        .broadcast_strategy
+                        .direct_public_key()
+                        .map(NodeId::from_public_key)
+                })
+                .map(|node_id| self.ordered_sends.join(node_id)),
+            _ => None,
+        };
+
         BroadcastTask::new(
             self.next.clone(),
             Arc::clone(&self.node_identity),
@@ -160,6 +313,14 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError> + Clo
             self.target_network,
             msg,
             self.message_validity_window,
+            Arc::clone(&self.sequence_counter),
+            self.interceptors.clone(),
+            self.max_concurrent_sends,
+            self.connectivity.clone(),
+            self.reject_without_connected_peers,
+            self.min_connected_peers_timeout,
+            self.min_connected_peers.clone(),
+            ordered_send,
         )
         .handle()
     }
@@ -173,12 +334,21 @@ struct BroadcastTask<S> {
     request: Option<DhtOutboundRequest>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    sequence_counter: Arc<dyn SequenceCounter>,
+    interceptors: Vec<Arc<dyn OutboundInterceptor>>,
+    max_concurrent_sends: usize,
+    connectivity: Option<ConnectivityRequester>,
+    reject_without_connected_peers: bool,
+    min_connected_peers_timeout: Duration,
+    min_connected_peers: MinConnectedPeersConfig,
+    ordered_send: Option<OrderedSend>,
 }
 type FinalMessageParts = (Option<Arc<CommsPublicKey>>, Option<Bytes>, Bytes);
 
 impl<S> BroadcastTask<S>
 where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         service: S,
         node_identity: Arc<NodeIdentity>,
@@ -187,6 +357,14 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
         target_network: Network,
         request: DhtOutboundRequest,
         message_validity_window: chrono::Duration,
+        sequence_counter: Arc<dyn SequenceCounter>,
+        interceptors: Vec<Arc<dyn OutboundInterceptor>>,
+        max_concurrent_sends: usize,
+        connectivity: Option<ConnectivityRequester>,
+        reject_without_connected_peers: bool,
+        min_connected_peers_timeout: Duration,
+        min_connected_peers: MinConnectedPeersConfig,
+        ordered_send: Option<OrderedSend>,
     ) -> Self
     {
         Self {
@@ -197,10 +375,23 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             target_network,
             request: Some(request),
             message_validity_window,
+            sequence_counter,
+            interceptors,
+            max_concurrent_sends,
+            connectivity,
+            reject_without_connected_peers,
+            min_connected_peers_timeout,
+            min_connected_peers,
+            ordered_send,
         }
     }
 
+    /// Processes the request. If the request uses ordered delivery, processing waits for the previous ordered request
+    /// to the same peer, and the next one is released when this task is dropped.
     pub async fn handle(mut self) -> Result<(), PipelineError> {
+        if let Some(ordered_send) = self.ordered_send.as_mut() {
+            ordered_send.wait_turn().await;
+        }
         let request = self.request.take().expect("request cannot be None");
         debug!(target: LOG_TARGET, "Processing outbound request {}", request);
         let messages = self.generate_outbound_messages(request).await?;
@@ -210,7 +401,8 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             messages.len()
         );
 
-        self.service
+        // A zero limit would never become ready, so at least one message is always allowed through
+        ConcurrencyLimit::new(&mut self.service, cmp::max(self.max_concurrent_sends, 1))
             .call_all(stream::iter(messages))
             .unordered()
             .filter_map(|result| future::ready(result.err()))
@@ -237,11 +429,19 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
 
     async fn handle_send_message(
         &mut self,
-        params: FinalSendMessageParams,
-        body: Bytes,
+        mut params: FinalSendMessageParams,
+        mut body: Bytes,
         reply_tx: oneshot::Sender<SendMessageResponse>,
     ) -> Result<Vec<DhtOutboundMessage>, DhtOutboundError>
     {
+        for interceptor in &self.interceptors {
+            if let Err(err) = interceptor.on_send(&mut params, &mut body) {
+                debug!(target: LOG_TARGET, "Outbound message intercepted: {}", err);
+                let _ = reply_tx.send(SendMessageResponse::Failed(SendFailure::Intercepted(err.to_string())));
+                return Err(err.into());
+            }
+        }
+
         trace!(target: LOG_TARGET, "Send params: {:?}", params);
         if params
             .broadcast_strategy
@@ -254,6 +454,22 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             return Err(DhtOutboundError::SendToOurselves);
         }
 
+        let min_connected_peers = params
+            .min_connected_peers
+            .unwrap_or_else(|| self.min_connected_peers.for_strategy(&params.broadcast_strategy));
+        if min_connected_peers > 0 {
+            self.wait_for_min_connected_peers(min_connected_peers).await;
+        }
+
+        if !params.broadcast_strategy.is_store_and_forward() && !self.has_connected_peers().await {
+            debug!(
+                target: LOG_TARGET,
+                "No peers are connected. Rejecting message sent using {}", params.broadcast_strategy
+            );
+            let _ = reply_tx.send(SendMessageResponse::Failed(SendFailure::NoConnectedPeers));
+            return Err(DhtOutboundError::NoConnectedPeers);
+        }
+
         let FinalSendMessageParams {
             broadcast_strategy,
             destination,
@@ -263,8 +479,22 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             is_discovery_enabled,
             force_origin,
             dht_header,
+            with_sequence_number,
+            trace_id,
+            min_connected_peers: _,
+            ordered_delivery: _,
+            cancellation,
         } = params;
 
+        if let Some(trace_id) = trace_id.as_ref() {
+            debug!(
+                target: LOG_TARGET,
+                "Outbound request with trace id {} ({})",
+                trace_id.to_hex(),
+                broadcast_strategy
+            );
+        }
+
         match self.select_peers(broadcast_strategy.clone()).await {
             Ok(mut peers) => {
                 if reply_tx.is_canceled() {
@@ -318,6 +548,20 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
                     }
                 }
 
+                if let Some(cancellation) = cancellation.as_ref() {
+                    if !cancellation.try_dispatch() {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Outbound request was cancelled before it was dispatched"
+                        );
+                        let _ = reply_tx
+                            .take()
+                            .expect("cannot fail")
+                            .send(SendMessageResponse::Failed(SendFailure::Cancelled));
+                        return Err(DhtOutboundError::Cancelled);
+                    }
+                }
+
                 let expires = Utc::now() + self.message_validity_window;
 
                 match self
@@ -330,6 +574,8 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
                         dht_message_flags,
                         force_origin,
                         is_broadcast,
+                        with_sequence_number,
+                        trace_id,
                         body,
                         Some(expires),
                     )
@@ -359,6 +605,74 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
         }
     }
 
+    /// Waits until at least `min_connected_peers` peers are connected or `min_connected_peers_timeout` expires,
+    /// whichever comes first. This returns immediately if the middleware was not given a `ConnectivityRequester` or
+    /// the active connections cannot be retrieved.
+    async fn wait_for_min_connected_peers(&mut self, min_connected_peers: usize) {
+        let timeout = self.min_connected_peers_timeout;
+        let connectivity = match self.connectivity.as_mut() {
+            Some(connectivity) => connectivity,
+            None => return,
+        };
+        let mut connectivity_events = connectivity.get_event_subscription();
+        let start = Instant::now();
+        loop {
+            let num_connected = match connectivity.get_active_connections().await {
+                Ok(conns) => conns.len(),
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "Unable to get active connections: {}", err);
+                    return;
+                },
+            };
+            if num_connected >= min_connected_peers {
+                return;
+            }
+
+            let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+            trace!(
+                target: LOG_TARGET,
+                "Waiting up to {:.2?} for {} connected peer(s) ({} connected)",
+                remaining,
+                min_connected_peers,
+                num_connected
+            );
+            // Any connectivity event may change the number of connected peers, so they are counted again
+            match time::timeout(remaining, connectivity_events.next()).await {
+                Ok(Some(_)) => continue,
+                Ok(None) => {
+                    warn!(target: LOG_TARGET, "Connectivity event stream closed");
+                    return;
+                },
+                Err(_) => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Timed out waiting for {} connected peer(s). Proceeding with {} connected peer(s)",
+                        min_connected_peers,
+                        num_connected
+                    );
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Returns false if the connected peers check is enabled and no peers are connected, otherwise true
+    async fn has_connected_peers(&mut self) -> bool {
+        if !self.reject_without_connected_peers {
+            return true;
+        }
+        match self.connectivity.as_mut() {
+            Some(connectivity) => match connectivity.get_active_connections().await {
+                Ok(conns) => !conns.is_empty(),
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "Unable to get active connections: {}", err);
+                    true
+                },
+            },
+            None => true,
+        }
+    }
+
     async fn select_peers(&mut self, broadcast_strategy: BroadcastStrategy) -> Result<Vec<NodeId>, DhtOutboundError> {
         self.dht_requester
             .select_peers(broadcast_strategy)
@@ -422,6 +736,8 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
         extra_flags: DhtMessageFlags,
         force_origin: bool,
         is_broadcast: bool,
+        with_sequence_number: bool,
+        trace_id: Option<Vec<u8>>,
         body: Bytes,
         expires: Option<DateTime<Utc>>,
     ) -> Result<(Vec<DhtOutboundMessage>, Vec<MessageSendState>), DhtOutboundError>
@@ -439,6 +755,11 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             let (reply_tx, reply_rx) = oneshot::channel();
             let tag = MessageTag::new();
             let send_state = MessageSendState::new(tag, reply_rx);
+            let sequence = if with_sequence_number {
+                Some(self.sequence_counter.next_sequence(&node_id))
+            } else {
+                None
+            };
             (
                 DhtOutboundMessage {
                     tag,
@@ -454,6 +775,8 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
                     origin_mac: origin_mac.clone(),
                     is_broadcast,
                     expires: expires.map(datetime_to_timestamp),
+                    sequence,
+                    trace_id: trace_id.clone(),
                 },
                 send_state,
             )
@@ -530,20 +853,36 @@ fn create_origin_mac(node_identity: &NodeIdentity, body: &[u8]) -> Result<Vec<u8
 mod test {
     use super::*;
     use crate::{
-        outbound::SendMessageParams,
-        test_utils::{create_dht_actor_mock, create_dht_discovery_mock, make_peer, service_spy, DhtDiscoveryMockState},
+        outbound::{CancelSendError, InterceptError, SendCancellationToken, SendMessageParams},
+        test_utils::{
+            create_dht_actor_mock,
+            create_dht_discovery_mock,
+            make_node_identity,
+            make_peer,
+            service_spy,
+            DhtDiscoveryMockState,
+        },
     };
     use futures::channel::oneshot;
     use rand::rngs::OsRng;
-    use std::time::Duration;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
     use tari_comms::{
+        connectivity::ConnectivityEvent,
         multiaddr::Multiaddr,
         peer_manager::{NodeId, Peer, PeerFeatures, PeerFlags},
+        test_utils::mocks::{create_connectivity_mock, create_dummy_peer_connection},
         types::CommsPublicKey,
     };
     use tari_crypto::keys::PublicKey;
     use tari_test_utils::unpack_enum;
     use tokio::task;
+    use tower::service_fn;
 
     #[tokio_macros::test_basic]
     async fn send_message_flood() {
@@ -612,6 +951,608 @@ mod test {
         assert!(requests.iter().any(|msg| msg.destination_node_id == other_peer.node_id));
     }
 
+    #[tokio_macros::test_basic]
+    async fn send_message_with_sequence_number() {
+        let peer = make_peer();
+        let node_identity = make_node_identity();
+
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            node_identity,
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        );
+
+        for _ in 0..2 {
+            let (reply_tx, _reply_rx) = oneshot::channel();
+            service
+                .call(DhtOutboundRequest::SendMessage(
+                    Box::new(
+                        SendMessageParams::new()
+                            .direct_node_id(peer.node_id.clone())
+                            .with_sequence_number()
+                            .finish(),
+                    ),
+                    Bytes::from_static(b"custom_msg"),
+                    reply_tx,
+                ))
+                .await
+                .unwrap();
+        }
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().direct_node_id(peer.node_id.clone()).finish()),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+
+        let requests = spy.take_requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].sequence, Some(1));
+        assert_eq!(requests[1].sequence, Some(2));
+        assert_eq!(requests[2].sequence, None);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_with_trace_id() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        );
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .direct_node_id(peer.node_id.clone())
+                        .with_trace_id(b"wallet-msg-1".to_vec())
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+
+        let requests = spy.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].trace_id, Some(b"wallet-msg-1".to_vec()));
+    }
+
+    struct MaxSizeInterceptor(usize);
+
+    impl OutboundInterceptor for MaxSizeInterceptor {
+        fn on_send(&self, _: &mut FinalSendMessageParams, body: &mut Bytes) -> Result<(), InterceptError> {
+            if body.len() > self.0 {
+                return Err(InterceptError::Rejected(format!("message exceeds {} bytes", self.0)));
+            }
+            Ok(())
+        }
+    }
+
+    struct TagInterceptor;
+
+    impl OutboundInterceptor for TagInterceptor {
+        fn on_send(&self, _: &mut FinalSendMessageParams, body: &mut Bytes) -> Result<(), InterceptError> {
+            let mut tagged = body.to_vec();
+            tagged.extend_from_slice(b"#tag");
+            *body = tagged.into();
+            Ok(())
+        }
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_interceptors() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_interceptors(vec![Arc::new(MaxSizeInterceptor(10)), Arc::new(TagInterceptor)]);
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let result = service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().direct_node_id(peer.node_id.clone()).finish()),
+                Bytes::from_static(b"this message is too long"),
+                reply_tx,
+            ))
+            .await;
+        assert!(result.is_err());
+        unpack_enum!(SendMessageResponse::Failed(failure) = reply_rx.await.unwrap());
+        unpack_enum!(SendFailure::Intercepted(reason) = failure);
+        assert!(reason.contains("exceeds 10 bytes"));
+        assert_eq!(spy.call_count(), 0);
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().direct_node_id(peer.node_id.clone()).finish()),
+                Bytes::from_static(b"short"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        let requests = spy.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].body, Bytes::from_static(b"short#tag"));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_without_connected_peers() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        connectivity_mock.spawn();
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_connected_peers_check(connectivity);
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let result = service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().direct_node_id(peer.node_id.clone()).finish()),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await;
+        assert!(result.is_err());
+        unpack_enum!(SendMessageResponse::Failed(failure) = reply_rx.await.unwrap());
+        unpack_enum!(SendFailure::NoConnectedPeers = failure);
+        assert_eq!(spy.call_count(), 0);
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .propagate(NodeDestination::Unknown, vec![])
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(spy.call_count(), 1);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_waits_for_min_connected_peers() {
+        let peers = (0..3).map(|_| make_peer()).collect::<Vec<_>>();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock.get_shared_state().set_select_peers_response(peers.clone());
+        task::spawn(dht_mock.run());
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        let connectivity_mock_state = connectivity_mock.get_shared_state();
+        connectivity_mock.spawn();
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_min_connected_peers_wait(connectivity, Duration::from_secs(60));
+
+        let connect_task = {
+            let spy = spy.clone();
+            task::spawn(async move {
+                for peer in peers {
+                    time::delay_for(Duration::from_millis(50)).await;
+                    assert_eq!(spy.call_count(), 0);
+                    let (conn, _) = create_dummy_peer_connection(peer.node_id);
+                    connectivity_mock_state.add_active_connection(conn.clone()).await;
+                    connectivity_mock_state.publish_event(ConnectivityEvent::PeerConnected(conn));
+                }
+            })
+        };
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .flood(vec![])
+                        .with_min_connected_peers(3)
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        connect_task.await.unwrap();
+        unpack_enum!(SendMessageResponse::Queued(send_states) = reply_rx.await.unwrap());
+        assert_eq!(send_states.len(), 3);
+        assert_eq!(spy.call_count(), 3);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_min_connected_peers_timeout() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        let (conn, _) = create_dummy_peer_connection(peer.node_id.clone());
+        connectivity_mock.get_shared_state().add_active_connection(conn).await;
+        connectivity_mock.spawn();
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_min_connected_peers_wait(connectivity, Duration::from_millis(100));
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .flood(vec![])
+                        .with_min_connected_peers(3)
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(spy.call_count(), 1);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_waits_for_min_connected_peers_for_strategy() {
+        let peers = (0..3).map(|_| make_peer()).collect::<Vec<_>>();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock.get_shared_state().set_select_peers_response(peers.clone());
+        task::spawn(dht_mock.run());
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        let connectivity_mock_state = connectivity_mock.get_shared_state();
+        connectivity_mock.spawn();
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_min_connected_peers_wait(connectivity, Duration::from_secs(60))
+        .with_min_connected_peers_defaults(MinConnectedPeersConfig {
+            flood: 3,
+            ..Default::default()
+        });
+
+        let connect_task = {
+            let spy = spy.clone();
+            task::spawn(async move {
+                for peer in peers {
+                    time::delay_for(Duration::from_millis(50)).await;
+                    assert_eq!(spy.call_count(), 0);
+                    let (conn, _) = create_dummy_peer_connection(peer.node_id);
+                    connectivity_mock_state.add_active_connection(conn.clone()).await;
+                    connectivity_mock_state.publish_event(ConnectivityEvent::PeerConnected(conn));
+                }
+            })
+        };
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().flood(vec![]).finish()),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        connect_task.await.unwrap();
+        unpack_enum!(SendMessageResponse::Queued(send_states) = reply_rx.await.unwrap());
+        assert_eq!(send_states.len(), 3);
+        assert_eq!(spy.call_count(), 3);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_min_connected_peers_overrides_strategy_default() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        let (conn, _) = create_dummy_peer_connection(peer.node_id.clone());
+        connectivity_mock.get_shared_state().add_active_connection(conn).await;
+        connectivity_mock.spawn();
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_min_connected_peers_wait(connectivity, Duration::from_secs(60))
+        .with_min_connected_peers_defaults(MinConnectedPeersConfig {
+            flood: 3,
+            ..Default::default()
+        });
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        // The message would wait for 60 seconds if the strategy default of 3 peers applied
+        time::timeout(
+            Duration::from_secs(5),
+            service.call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .flood(vec![])
+                        .with_min_connected_peers(1)
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            )),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(spy.call_count(), 1);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_max_concurrent_sends() {
+        let peers = (0..100).map(|_| make_peer()).collect::<Vec<_>>();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock.get_shared_state().set_select_peers_response(peers);
+        task::spawn(dht_mock.run());
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let num_sent = Arc::new(AtomicUsize::new(0));
+        let next_service = {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            let num_sent = num_sent.clone();
+            service_fn(move |_: DhtOutboundMessage| {
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                let num_sent = num_sent.clone();
+                async move {
+                    let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(n, Ordering::SeqCst);
+                    for _ in 0..5 {
+                        task::yield_now().await;
+                    }
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    num_sent.fetch_add(1, Ordering::SeqCst);
+                    Result::<_, PipelineError>::Ok(())
+                }
+            })
+        };
+
+        let mut service = BroadcastMiddleware::new(
+            next_service,
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_max_concurrent_sends(10);
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(SendMessageParams::new().flood(vec![]).finish()),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(num_sent.load(Ordering::SeqCst), 100);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_ordered_delivery() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+
+        let dispatched = Arc::new(Mutex::new(Vec::new()));
+        let next_service = {
+            let dispatched = dispatched.clone();
+            service_fn(move |msg: DhtOutboundMessage| {
+                let dispatched = dispatched.clone();
+                async move {
+                    // Simulate a slow dispatch of the first message
+                    if msg.body == Bytes::from_static(b"first") {
+                        time::delay_for(Duration::from_millis(100)).await;
+                    }
+                    dispatched.lock().unwrap().push(msg.body);
+                    Result::<_, PipelineError>::Ok(())
+                }
+            })
+        };
+
+        let mut service = BroadcastMiddleware::new(
+            next_service,
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        );
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        let first = service.call(DhtOutboundRequest::SendMessage(
+            Box::new(
+                SendMessageParams::new()
+                    .direct_node_id(peer.node_id.clone())
+                    .with_ordered_delivery()
+                    .finish(),
+            ),
+            Bytes::from_static(b"first"),
+            reply_tx,
+        ));
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        let second = service.call(DhtOutboundRequest::SendMessage(
+            Box::new(
+                SendMessageParams::new()
+                    .direct_node_id(peer.node_id.clone())
+                    .with_ordered_delivery()
+                    .finish(),
+            ),
+            Bytes::from_static(b"second"),
+            reply_tx,
+        ));
+
+        // The second request is polled first but may only be dispatched after the first
+        let (second_result, first_result) = future::join(second, first).await;
+        first_result.unwrap();
+        second_result.unwrap();
+        assert_eq!(*dispatched.lock().unwrap(), vec![
+            Bytes::from_static(b"first"),
+            Bytes::from_static(b"second")
+        ]);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_cancellation() {
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+
+        let spy = service_spy();
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            make_node_identity(),
+            dht_requester,
+            dht_discover_requester,
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        );
+
+        let token = SendCancellationToken::new();
+        token.cancel().unwrap();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let result = service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .direct_node_id(peer.node_id.clone())
+                        .with_cancellation(token)
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await;
+        assert!(result.is_err());
+        unpack_enum!(SendMessageResponse::Failed(failure) = reply_rx.await.unwrap());
+        unpack_enum!(SendFailure::Cancelled = failure);
+        assert_eq!(spy.call_count(), 0);
+
+        let token = SendCancellationToken::new();
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .direct_node_id(peer.node_id.clone())
+                        .with_cancellation(token.clone())
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(spy.call_count(), 1);
+        assert_eq!(token.cancel().unwrap_err(), CancelSendError::AlreadyDispatched);
+    }
+
     #[tokio_macros::test_basic]
     async fn send_message_direct_not_found() {
         // Test for issue https://github.com/tari-project/tari/issues/959
@@ -0,0 +1,104 @@
+// Copyright 2021, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+use thiserror::Error;
+
+const PENDING: u8 = 0;
+const CANCELLED: u8 = 1;
+const DISPATCHED: u8 = 2;
+
+/// Allows an outbound message request to be cancelled before the broadcast middleware dispatches it (see
+/// `SendMessageParams::with_cancellation`). Clones share the same cancellation state.
+#[derive(Debug, Default, Clone)]
+pub struct SendCancellationToken {
+    state: Arc<AtomicU8>,
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum CancelSendError {
+    #[error("The message has already been dispatched and cannot be recalled")]
+    AlreadyDispatched,
+}
+
+impl SendCancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cancel the message if it has not been dispatched yet. Cancelling more than once has no further effect.
+    pub fn cancel(&self) -> Result<(), CancelSendError> {
+        match self
+            .state
+            .compare_exchange(PENDING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) | Err(CANCELLED) => Ok(()),
+            Err(_) => Err(CancelSendError::AlreadyDispatched),
+        }
+    }
+
+    /// Returns true if the message was cancelled before being dispatched
+    pub fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == CANCELLED
+    }
+
+    /// Returns true if the message has been dispatched
+    pub fn is_dispatched(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == DISPATCHED
+    }
+
+    /// Mark the message as dispatched. Returns false if the message was cancelled, in which case it must not be sent.
+    pub(super) fn try_dispatch(&self) -> bool {
+        match self
+            .state
+            .compare_exchange(PENDING, DISPATCHED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) | Err(DISPATCHED) => true,
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cancel() {
+        let token = SendCancellationToken::new();
+        let other = token.clone();
+        token.cancel().unwrap();
+        token.cancel().unwrap();
+        assert!(other.is_cancelled());
+        assert!(!other.try_dispatch());
+        assert!(!other.is_dispatched());
+
+        let token = SendCancellationToken::new();
+        assert!(token.try_dispatch());
+        assert!(token.is_dispatched());
+        assert_eq!(token.cancel().unwrap_err(), CancelSendError::AlreadyDispatched);
+        assert!(!token.is_cancelled());
+    }
+}
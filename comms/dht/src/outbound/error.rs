@@ -20,7 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::outbound::message::SendFailure;
+use crate::outbound::{message::SendFailure, InterceptError};
 use futures::channel::mpsc::SendError;
 use tari_comms::message::MessageError;
 use tari_crypto::{
@@ -59,12 +59,20 @@ pub enum DhtOutboundError {
     SendMessageFailed(SendFailure),
     #[error("No messages were queued for sending")]
     NoMessagesQueued,
+    #[error("Message rejected by outbound interceptor: {0}")]
+    Intercepted(#[from] InterceptError),
+    #[error("No peers are connected")]
+    NoConnectedPeers,
+    #[error("Message was cancelled before it was sent")]
+    Cancelled,
 }
 
 impl From<SendFailure> for DhtOutboundError {
     fn from(err: SendFailure) -> Self {
         match err {
             SendFailure::NoMessagesQueued => DhtOutboundError::NoMessagesQueued,
+            SendFailure::NoConnectedPeers => DhtOutboundError::NoConnectedPeers,
+            SendFailure::Cancelled => DhtOutboundError::Cancelled,
             err => Self::SendMessageFailed(err),
         }
     }
@@ -92,6 +92,12 @@ pub enum SendFailure {
     FailedToGenerateMessages(String),
     #[error("No messages were queued for sending")]
     NoMessagesQueued,
+    #[error("Message rejected by outbound interceptor: {0}")]
+    Intercepted(String),
+    #[error("No peers are connected")]
+    NoConnectedPeers,
+    #[error("Message was cancelled before it was sent")]
+    Cancelled,
 }
 
 #[derive(Debug)]
@@ -168,6 +174,9 @@ pub struct DhtOutboundMessage {
     pub dht_flags: DhtMessageFlags,
     pub is_broadcast: bool,
     pub expires: Option<prost_types::Timestamp>,
+    pub sequence: Option<u64>,
+    /// Local-only id used to trace the message in logs, see `SendMessageParams::with_trace_id`
+    pub trace_id: Option<Vec<u8>>,
 }
 
 impl fmt::Display for DhtOutboundMessage {
@@ -184,12 +193,16 @@ impl fmt::Display for DhtOutboundMessage {
             });
         write!(
             f,
-            "\n---- Outgoing message ---- \nSize: {} byte(s)\nType: {}\nPeer: {}\nHeader: {}\n{}\n----",
+            "\n---- Outgoing message ---- \nSize: {} byte(s)\nType: {}\nPeer: {}\nHeader: {}\n{}\nTrace id: {}\n----",
             self.body.len(),
             self.dht_message_type,
             self.destination_node_id,
             header_str,
             self.tag,
+            self.trace_id
+                .as_ref()
+                .map(|id| id.to_hex())
+                .unwrap_or_else(|| "None".to_string()),
         )
     }
 }
@@ -23,12 +23,15 @@
 use crate::{
     broadcast_strategy::{BroadcastClosestRequest, BroadcastStrategy},
     envelope::{DhtMessageFlags, DhtMessageHeader, NodeDestination},
-    outbound::OutboundEncryption,
+    outbound::{OutboundEncryption, SendCancellationToken},
     proto::envelope::DhtMessageType,
 };
+use log::*;
 use std::{fmt, fmt::Display};
 use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
 
+const LOG_TARGET: &str = "comms::dht::outbound::message_params";
+
 /// Configuration for outbound messages.
 ///
 /// ```edition2018
@@ -46,12 +49,14 @@ use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
 #[derive(Debug, Clone)]
 pub struct SendMessageParams {
     params: Option<FinalSendMessageParams>,
+    is_broadcast_strategy_set: bool,
 }
 
 impl Default for SendMessageParams {
     fn default() -> Self {
         Self {
             params: Some(Default::default()),
+            is_broadcast_strategy_set: false,
         }
     }
 }
@@ -66,6 +71,20 @@ pub struct FinalSendMessageParams {
     pub dht_message_type: DhtMessageType,
     pub dht_message_flags: DhtMessageFlags,
     pub dht_header: Option<DhtMessageHeader>,
+    pub with_sequence_number: bool,
+    /// Application-defined id used to trace the message through the outbound pipeline logs. This is never sent to
+    /// peers.
+    pub trace_id: Option<Vec<u8>>,
+    /// The number of peers that must be connected before the message is sent. The broadcast middleware waits up to
+    /// `DhtConfig::min_connected_peers_timeout` for them to connect and then sends the message to the peers that are
+    /// available. If this is None, the value in `DhtConfig::min_connected_peers` for the broadcast strategy is used.
+    pub min_connected_peers: Option<usize>,
+    /// If true, this request is not processed until earlier ordered requests to the same peer have been dispatched or
+    /// have failed. Only applies to direct broadcast strategies.
+    pub ordered_delivery: bool,
+    /// If set, the request is dropped without being sent if the token is cancelled before the broadcast middleware
+    /// dispatches it
+    pub cancellation: Option<SendCancellationToken>,
 }
 
 impl Default for FinalSendMessageParams {
@@ -79,6 +98,11 @@ impl Default for FinalSendMessageParams {
             force_origin: false,
             is_discovery_enabled: false,
             dht_header: None,
+            with_sequence_number: false,
+            trace_id: None,
+            min_connected_peers: None,
+            ordered_delivery: false,
+            cancellation: None,
         }
     }
 }
@@ -100,13 +124,13 @@ impl SendMessageParams {
 
     /// Set broadcast_strategy to DirectPublicKey
     pub fn direct_public_key(&mut self, public_key: CommsPublicKey) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::DirectPublicKey(Box::new(public_key));
+        self.set_broadcast_strategy(BroadcastStrategy::DirectPublicKey(Box::new(public_key)));
         self
     }
 
     /// Set broadcast_strategy to DirectNodeId
     pub fn direct_node_id(&mut self, node_id: NodeId) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::DirectNodeId(Box::new(node_id));
+        self.set_broadcast_strategy(BroadcastStrategy::DirectNodeId(Box::new(node_id)));
         self
     }
 
@@ -116,46 +140,46 @@ impl SendMessageParams {
     /// `node_id` - Select the closest known peers to this `NodeId`
     /// `excluded_peers` - vector of `NodeId`s to exclude from broadcast.
     pub fn closest(&mut self, node_id: NodeId, excluded_peers: Vec<NodeId>) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
+        self.set_broadcast_strategy(BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
             excluded_peers,
             node_id,
             connected_only: false,
-        }));
+        })));
         self
     }
 
     /// Set broadcast_strategy to Closest.`excluded_peers` are excluded. Only peers that are currently connected will be
     /// included.
     pub fn closest_connected(&mut self, node_id: NodeId, excluded_peers: Vec<NodeId>) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
+        self.set_broadcast_strategy(BroadcastStrategy::Closest(Box::new(BroadcastClosestRequest {
             excluded_peers,
             node_id,
             connected_only: true,
-        }));
+        })));
         self
     }
 
     /// Set broadcast_strategy to Neighbours. `excluded_peers` are excluded. Only Peers that have
     /// `PeerFeatures::MESSAGE_PROPAGATION` are included.
     pub fn broadcast(&mut self, excluded_peers: Vec<NodeId>) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Broadcast(excluded_peers);
+        self.set_broadcast_strategy(BroadcastStrategy::Broadcast(excluded_peers));
         self
     }
 
     pub fn propagate(&mut self, destination: NodeDestination, excluded_peers: Vec<NodeId>) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Propagate(destination, excluded_peers);
+        self.set_broadcast_strategy(BroadcastStrategy::Propagate(destination, excluded_peers));
         self
     }
 
     /// Set broadcast_strategy to Flood
     pub fn flood(&mut self, excluded: Vec<NodeId>) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Flood(excluded);
+        self.set_broadcast_strategy(BroadcastStrategy::Flood(excluded));
         self
     }
 
     /// Set broadcast_strategy to Random.
     pub fn random(&mut self, n: usize) -> &mut Self {
-        self.params_mut().broadcast_strategy = BroadcastStrategy::Random(n, vec![]);
+        self.set_broadcast_strategy(BroadcastStrategy::Random(n, vec![]));
         self
     }
 
@@ -189,6 +213,41 @@ impl SendMessageParams {
         self
     }
 
+    /// Include a sequence number in the header of each message. Sequence numbers increase monotonically for each
+    /// receiving peer, allowing the receiver to detect missing messages.
+    pub fn with_sequence_number(&mut self) -> &mut Self {
+        self.params_mut().with_sequence_number = true;
+        self
+    }
+
+    /// Attach an application-defined trace id to the outbound message(s). The trace id is included in log output at
+    /// each stage of the outbound pipeline and is not transmitted.
+    pub fn with_trace_id(&mut self, trace_id: Vec<u8>) -> &mut Self {
+        self.params_mut().trace_id = Some(trace_id);
+        self
+    }
+
+    /// Wait until at least `min_connected_peers` peers are connected before sending the message(s). This overrides the
+    /// value configured for the broadcast strategy in `DhtConfig::min_connected_peers`.
+    pub fn with_min_connected_peers(&mut self, min_connected_peers: usize) -> &mut Self {
+        self.params_mut().min_connected_peers = Some(min_connected_peers);
+        self
+    }
+
+    /// Dispatch the message after any earlier ordered messages to the same peer. This only applies to messages sent
+    /// using `direct_public_key` or `direct_node_id`; other strategies ignore it.
+    pub fn with_ordered_delivery(&mut self) -> &mut Self {
+        self.params_mut().ordered_delivery = true;
+        self
+    }
+
+    /// Allow the message(s) to be cancelled using the given token until the broadcast middleware dispatches them. Once
+    /// dispatched, `SendCancellationToken::cancel` returns `CancelSendError::AlreadyDispatched`.
+    pub fn with_cancellation(&mut self, token: SendCancellationToken) -> &mut Self {
+        self.params_mut().cancellation = Some(token);
+        self
+    }
+
     /// Override the DHtHeader of a message(s) with the given header
     pub fn with_dht_header(&mut self, dht_header: DhtMessageHeader) -> &mut Self {
         self.params_mut().dht_header = Some(dht_header);
@@ -202,12 +261,69 @@ impl SendMessageParams {
         self
     }
 
-    /// Return the final SendMessageParams
+    /// Return the final SendMessageParams. If no broadcast strategy was set, the default strategy for the DHT message
+    /// type is used (see `BroadcastStrategy::default_for`).
+    ///
+    /// Reply message types have no default strategy and must be sent directly to a peer. If no strategy was set for
+    /// one of these, an error is logged and the message is not sent to any peers.
     pub fn finish(&mut self) -> FinalSendMessageParams {
-        self.params.take().expect("cannot be None")
+        let mut params = self.params.take().expect("cannot be None");
+        if !self.is_broadcast_strategy_set {
+            match BroadcastStrategy::default_for(params.dht_message_type) {
+                Some(strategy) => {
+                    params.broadcast_strategy = strategy;
+                },
+                None => {
+                    error!(
+                        target: LOG_TARGET,
+                        "A direct broadcast strategy must be set for {:?} messages. The message will not be sent.",
+                        params.dht_message_type
+                    );
+                    params.broadcast_strategy = BroadcastStrategy::Random(0, Vec::new());
+                },
+            }
+        }
+        params
+    }
+
+    fn set_broadcast_strategy(&mut self, broadcast_strategy: BroadcastStrategy) {
+        self.params_mut().broadcast_strategy = broadcast_strategy;
+        self.is_broadcast_strategy_set = true;
     }
 
     fn params_mut(&mut self) -> &mut FinalSendMessageParams {
         self.params.as_mut().expect("cannot be None")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finish_uses_default_strategy() {
+        let params = SendMessageParams::new()
+            .with_dht_message_type(DhtMessageType::Join)
+            .finish();
+        assert!(matches!(params.broadcast_strategy, BroadcastStrategy::Broadcast(_)));
+
+        let params = SendMessageParams::new()
+            .direct_public_key(CommsPublicKey::default())
+            .with_dht_message_type(DhtMessageType::DiscoveryResponse)
+            .finish();
+        assert!(params.broadcast_strategy.is_direct());
+    }
+
+    #[test]
+    fn finish_requires_strategy_for_replies() {
+        let params = SendMessageParams::new()
+            .with_dht_message_type(DhtMessageType::SafStoredMessages)
+            .finish();
+        assert!(matches!(params.broadcast_strategy, BroadcastStrategy::Random(0, _)));
+
+        let params = SendMessageParams::new()
+            .with_dht_message_type(DhtMessageType::DiscoveryResponse)
+            .finish();
+        assert!(matches!(params.broadcast_strategy, BroadcastStrategy::Random(0, _)));
+    }
+}
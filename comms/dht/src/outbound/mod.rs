@@ -23,21 +23,32 @@
 mod broadcast;
 pub use broadcast::BroadcastLayer;
 
+mod cancellation;
+pub use cancellation::{CancelSendError, SendCancellationToken};
+
 mod error;
 pub use error::DhtOutboundError;
 
 pub(crate) mod message;
 pub use message::{DhtOutboundRequest, OutboundEncryption, SendMessageResponse};
 
+mod interceptor;
+pub use interceptor::{InterceptError, OutboundInterceptor};
+
 mod message_params;
-pub use message_params::SendMessageParams;
+pub use message_params::{FinalSendMessageParams, SendMessageParams};
 
 mod message_send_state;
 pub use message_send_state::{MessageSendState, MessageSendStates};
 
+mod ordering;
+
 mod requester;
 pub use requester::OutboundMessageRequester;
 
+mod sequence;
+pub use sequence::{InMemorySequenceCounter, SequenceCounter};
+
 mod serialize;
 pub use serialize::SerializeLayer;
 
@@ -0,0 +1,138 @@
+// Copyright 2021, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use futures::channel::oneshot;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tari_comms::peer_manager::NodeId;
+
+/// Per-peer FIFO queues for outbound requests that use ordered delivery (see
+/// `SendMessageParams::with_ordered_delivery`). A request joins the queue for its peer when it is submitted and is
+/// processed once every request that joined before it has completed.
+#[derive(Debug, Default, Clone)]
+pub struct OrderedSendQueues {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    /// The completion signal of the last request in each queue, tagged with the id of that request
+    tails: HashMap<NodeId, (u64, oneshot::Receiver<()>)>,
+}
+
+impl OrderedSendQueues {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a request to the back of the queue for `node_id`
+    pub fn join(&self, node_id: NodeId) -> OrderedSend {
+        let (done_tx, done_rx) = oneshot::channel();
+        let mut inner = acquire_lock!(self.inner);
+        let id = inner.next_id;
+        inner.next_id = inner.next_id.wrapping_add(1);
+        let prev = inner.tails.insert(node_id.clone(), (id, done_rx)).map(|(_, rx)| rx);
+        OrderedSend {
+            queues: self.clone(),
+            node_id,
+            id,
+            prev,
+            _done_tx: done_tx,
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        acquire_lock!(self.inner).tails.len()
+    }
+}
+
+/// A place in an `OrderedSendQueues` queue. Dropping it allows the next request in the queue to proceed.
+#[derive(Debug)]
+pub struct OrderedSend {
+    queues: OrderedSendQueues,
+    node_id: NodeId,
+    id: u64,
+    prev: Option<oneshot::Receiver<()>>,
+    _done_tx: oneshot::Sender<()>,
+}
+
+impl OrderedSend {
+    /// Waits until the request ahead of this one has completed
+    pub async fn wait_turn(&mut self) {
+        if let Some(prev) = self.prev.take() {
+            // The sender is only ever dropped, so this always resolves to Canceled
+            let _ = prev.await;
+        }
+    }
+}
+
+impl Drop for OrderedSend {
+    fn drop(&mut self) {
+        let mut inner = acquire_lock!(self.queues.inner);
+        // Remove the queue if no other request has joined it since this one
+        if inner
+            .tails
+            .get(&self.node_id)
+            .filter(|(id, _)| *id == self.id)
+            .is_some()
+        {
+            inner.tails.remove(&self.node_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+    use futures::{future::FutureExt, pin_mut};
+
+    #[tokio_macros::test_basic]
+    async fn join() {
+        let queues = OrderedSendQueues::new();
+        let node_id1 = make_node_identity().node_id().clone();
+        let node_id2 = make_node_identity().node_id().clone();
+
+        let mut first = queues.join(node_id1.clone());
+        let mut second = queues.join(node_id1);
+        let mut other = queues.join(node_id2);
+        assert_eq!(queues.len(), 2);
+
+        first.wait_turn().await;
+        other.wait_turn().await;
+        {
+            let wait_turn = second.wait_turn();
+            pin_mut!(wait_turn);
+            assert!(wait_turn.as_mut().now_or_never().is_none());
+            drop(first);
+            wait_turn.await;
+        }
+
+        drop(second);
+        drop(other);
+        assert_eq!(queues.len(), 0);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tari_comms::peer_manager::NodeId;
+
+/// Source of per-destination sequence numbers for outbound messages that request one (see
+/// `SendMessageParams::with_sequence_number`). Implementations may persist the counters so that sequences continue
+/// across restarts.
+pub trait SequenceCounter: Send + Sync {
+    /// Returns the next sequence number for messages sent to `destination`. Sequence numbers start at 1, as 0 means
+    /// that no sequence number was set.
+    fn next_sequence(&self, destination: &NodeId) -> u64;
+}
+
+/// `SequenceCounter` that keeps its counters in memory. Sequences start again from 1 after a restart.
+#[derive(Debug, Default, Clone)]
+pub struct InMemorySequenceCounter {
+    counters: Arc<Mutex<HashMap<NodeId, u64>>>,
+}
+
+impl InMemorySequenceCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl SequenceCounter for InMemorySequenceCounter {
+    fn next_sequence(&self, destination: &NodeId) -> u64 {
+        let mut counters = acquire_lock!(self.counters);
+        let counter = counters.entry(destination.clone()).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+
+    #[test]
+    fn next_sequence() {
+        let counter = InMemorySequenceCounter::new();
+        let node_id1 = make_node_identity().node_id().clone();
+        let node_id2 = make_node_identity().node_id().clone();
+        assert_eq!(counter.next_sequence(&node_id1), 1);
+        assert_eq!(counter.next_sequence(&node_id1), 2);
+        assert_eq!(counter.next_sequence(&node_id2), 1);
+        assert_eq!(counter.next_sequence(&node_id1), 3);
+    }
+}
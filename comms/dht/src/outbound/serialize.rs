@@ -33,7 +33,7 @@ use tari_comms::{
     pipeline::PipelineError,
     Bytes,
 };
-use tari_utilities::ByteArray;
+use tari_utilities::{hex::Hex, ByteArray};
 use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::serialize";
@@ -77,13 +77,19 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
                 origin_mac,
                 reply,
                 expires,
+                sequence,
+                trace_id,
                 ..
             } = message;
             trace!(
                 target: LOG_TARGET,
-                "Serializing outbound message {:?} for peer `{}`",
+                "Serializing outbound message {:?} for peer `{}` (Trace id: {})",
                 message.tag,
-                destination_node_id.short_str()
+                destination_node_id.short_str(),
+                trace_id
+                    .as_ref()
+                    .map(|id| id.to_hex())
+                    .unwrap_or_else(|| "None".to_string())
             );
             let dht_header = custom_header.map(DhtHeader::from).unwrap_or_else(|| DhtHeader {
                 version: DHT_ENVELOPE_HEADER_VERSION,
@@ -95,6 +101,7 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
                 destination: Some(destination.into()),
                 message_tag: tag.as_value(),
                 expires,
+                sequence: sequence.unwrap_or(0),
             });
             let envelope = DhtEnvelope::new(dht_header, body);
 
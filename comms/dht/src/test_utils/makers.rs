@@ -91,6 +91,7 @@ pub fn make_dht_header(
         flags,
         message_tag: trace,
         expires: None,
+        sequence: None,
     }
 }
 
@@ -197,5 +198,7 @@ pub fn create_outbound_message(body: &[u8]) -> DhtOutboundMessage {
         origin_mac: None,
         is_broadcast: false,
         expires: None,
+        sequence: None,
+        trace_id: None,
     }
 }